@@ -19,14 +19,82 @@ fn main() {
         .whitelist_var("BTN_TL2")
         .whitelist_var("BTN_TR")
         .whitelist_var("BTN_TR2")
+        .whitelist_var("BTN_MODE")
+        .whitelist_var("KEY_RECORD")
+        .whitelist_var("BTN_JOYSTICK")
+        .whitelist_var("BTN_TRIGGER_HAPPY1")
+        .whitelist_var("BTN_TRIGGER_HAPPY5")
+        .whitelist_var("BTN_TRIGGER_HAPPY6")
+        .whitelist_var("BTN_TRIGGER_HAPPY7")
+        .whitelist_var("BTN_TRIGGER_HAPPY8")
         .whitelist_var("EV_FF")
         .whitelist_var("FF_RUMBLE")
+        .whitelist_var("FF_CONSTANT")
+        .whitelist_var("FF_SPRING")
+        .whitelist_var("FF_DAMPER")
+        .whitelist_var("FF_GAIN")
+        .whitelist_var("FF_AUTOCENTER")
         .whitelist_var("FF_MAX_EFFECTS")
         .whitelist_var("EV_ABS")
         .whitelist_var("ABS_X")
         .whitelist_var("ABS_Y")
         .whitelist_var("ABS_RX")
         .whitelist_var("ABS_RY")
+        .whitelist_var("ABS_Z")
+        .whitelist_var("ABS_RZ")
+        .whitelist_var("ABS_THROTTLE")
+        .whitelist_var("ABS_RUDDER")
+        .whitelist_var("ABS_WHEEL")
+        .whitelist_var("ABS_GAS")
+        .whitelist_var("ABS_BRAKE")
+        .whitelist_var("ABS_HAT0X")
+        .whitelist_var("ABS_HAT0Y")
+        .whitelist_var("ABS_HAT1X")
+        .whitelist_var("ABS_HAT1Y")
+        .whitelist_var("ABS_HAT2X")
+        .whitelist_var("ABS_HAT2Y")
+        .whitelist_var("ABS_HAT3X")
+        .whitelist_var("ABS_HAT3Y")
+        .whitelist_var("ABS_PRESSURE")
+        .whitelist_var("ABS_DISTANCE")
+        .whitelist_var("ABS_TILT_X")
+        .whitelist_var("ABS_TILT_Y")
+        .whitelist_var("ABS_TOOL_WIDTH")
+        .whitelist_var("ABS_VOLUME")
+        .whitelist_var("ABS_MISC")
+        .whitelist_var("EV_MSC")
+        .whitelist_var("MSC_TIMESTAMP")
+        .whitelist_var("EV_LED")
+        .whitelist_var("LED_NUML")
+        .whitelist_var("LED_CAPSL")
+        .whitelist_var("LED_SCROLLL")
+        .whitelist_var("EV_REL")
+        .whitelist_var("REL_X")
+        .whitelist_var("REL_Y")
+        .whitelist_var("REL_WHEEL")
+        .whitelist_var("REL_HWHEEL")
+        .whitelist_var("REL_WHEEL_HI_RES")
+        .whitelist_var("REL_HWHEEL_HI_RES")
+        .whitelist_var("BTN_RIGHT")
+        .whitelist_var("BTN_MIDDLE")
+        .whitelist_var("BTN_SIDE")
+        .whitelist_var("BTN_EXTRA")
+        .whitelist_var("INPUT_PROP_DIRECT")
+        .whitelist_var("INPUT_PROP_ACCELEROMETER")
+        .whitelist_var("INPUT_PROP_POINTER")
+        .whitelist_var("INPUT_PROP_BUTTONPAD")
+        .whitelist_var("ABS_MT_SLOT")
+        .whitelist_var("ABS_MT_TRACKING_ID")
+        .whitelist_var("ABS_MT_POSITION_X")
+        .whitelist_var("ABS_MT_POSITION_Y")
+        .whitelist_var("BTN_LEFT")
+        .whitelist_var("BTN_TOUCH")
+        .whitelist_var("BTN_TOOL_PEN")
+        .whitelist_var("BTN_TOOL_FINGER")
+        .whitelist_var("BTN_TOOL_DOUBLETAP")
+        .whitelist_var("BTN_TOOL_TRIPLETAP")
+        .whitelist_var("BTN_TOOL_QUADTAP")
+        .whitelist_var("BTN_TOOL_QUINTTAP")
         .whitelist_var("EV_SYN")
         .whitelist_var("SYN_REPORT")
         .whitelist_var("EV_UINPUT")
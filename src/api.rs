@@ -1,4 +1,10 @@
-#[derive(Debug)]
+use std::time::Duration;
+
+use crate::force_feedback::{HdRumble, PlayedEffect, TriggerEffect};
+use crate::keyboard::Key;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Button {
     DpadDown,
     DpadUp,
@@ -16,15 +22,136 @@ pub enum Button {
     TriggerRight2,
     ThumbStickLeft,
     ThumbStickRight,
+    /// Guide, PS or Home button.
+    Mode,
+    /// Share, Create or Capture button.
+    Capture,
+    /// Back paddles, numbered like on the Xbox Elite controller.
+    Paddle1,
+    Paddle2,
+    Paddle3,
+    Paddle4,
+    /// The `n`th button of a generic joystick, counting from 0.
+    ///
+    /// Numbered buttons are pressed and released like every other button
+    /// rather than through an input of their own, so that turbo, macros and
+    /// resets handle them as well.
+    Numbered(u8),
+    /// A key of a keyboard.
+    Key(Key),
+    MouseLeft,
+    MouseRight,
+    MouseMiddle,
+    /// The back button on the side of a mouse.
+    MouseSide,
+    /// The forward button on the side of a mouse.
+    MouseExtra,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ThumbStick {
     Left,
     Right,
 }
 
-#[derive(Debug)]
+/// One of the analog triggers of a gamepad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Trigger {
+    Left,
+    Right,
+}
+
+/// An absolute axis of the device, named after its evdev counterpart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Axis {
+    /// Left thumb stick, horizontal.
+    X,
+    /// Left thumb stick, vertical.
+    Y,
+    Z,
+    /// Right thumb stick, horizontal.
+    Rx,
+    /// Right thumb stick, vertical.
+    Ry,
+    Rz,
+    Throttle,
+    Rudder,
+    Wheel,
+    Gas,
+    Brake,
+    Hat0X,
+    Hat0Y,
+    Hat1X,
+    Hat1Y,
+    Hat2X,
+    Hat2Y,
+    Hat3X,
+    Hat3Y,
+    Pressure,
+    Distance,
+    TiltX,
+    TiltY,
+    ToolWidth,
+    Volume,
+    Misc,
+}
+
+impl Axis {
+    /// The horizontal and vertical axes of hat `n`, for `n` in `0..4`.
+    pub fn hat(n: u8) -> Option<(Axis, Axis)> {
+        match n {
+            0 => Some((Axis::Hat0X, Axis::Hat0Y)),
+            1 => Some((Axis::Hat1X, Axis::Hat1Y)),
+            2 => Some((Axis::Hat2X, Axis::Hat2Y)),
+            3 => Some((Axis::Hat3X, Axis::Hat3Y)),
+            _ => None,
+        }
+    }
+}
+
+impl ThumbStick {
+    pub fn axes(self) -> (Axis, Axis) {
+        match self {
+            ThumbStick::Left => (Axis::X, Axis::Y),
+            ThumbStick::Right => (Axis::Rx, Axis::Ry),
+        }
+    }
+}
+
+/// The range of an axis as the device was set up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AxisInfo {
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+impl AxisInfo {
+    /// Scales a value in `-1.0..=1.0` to this axis' range. The positive half
+    /// maps onto `0..=maximum` and the negative half onto `minimum..=0`.
+    pub fn scale(&self, value: f32) -> i32 {
+        if value >= 0.0 {
+            (value * self.maximum as f32) as i32
+        } else {
+            (-value * self.minimum as f32) as i32
+        }
+    }
+
+    /// The value of the axis at rest: zero, or the closest end of the range
+    /// for axes that do not cross zero.
+    pub fn neutral(&self) -> i32 {
+        0.clamp(self.minimum, self.maximum)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Input {
     Press(Button),
     Release(Button),
@@ -33,19 +160,135 @@ pub enum Input {
         x: f32,
         y: f32,
     },
+    /// Writes `value` to `axis` as is. Neither the stick processing nor
+    /// scaling is applied, only the value policy against the axis' range.
+    ///
+    /// Axes are named instead of given by their evdev code, so that every
+    /// platform can tell which of them it supports.
+    Axis {
+        axis: Axis,
+        value: i32,
+    },
+    /// A reading of the motion sensors. `accel` is in g and `gyro` in degrees
+    /// per second, both along X, Y and Z. Needs a device plugged in with
+    /// motion sensors.
+    Motion {
+        accel: [f32; 3],
+        gyro: [f32; 3],
+    },
+    /// Puts `finger` down on the touchpad, or moves it there. `x` and `y` are
+    /// in `0.0..=1.0` from the top left corner. A pointer device is touched
+    /// with finger 0.
+    Touch {
+        finger: u8,
+        x: f32,
+        y: f32,
+    },
+    /// Lifts `finger` off the touchpad.
+    TouchUp {
+        finger: u8,
+    },
+    TouchpadClick {
+        pressed: bool,
+    },
+    /// Moves the pointer of a mouse by `x` and `y` counts, to the right and
+    /// down.
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+    /// Turns the scroll wheels of a mouse, in 120ths of a notch. Positive
+    /// values scroll up and to the right.
+    Scroll {
+        vertical: i32,
+        horizontal: i32,
+    },
 }
 
+/// Identifies a device among the ones plugged in by the same bus. Doubles as
+/// the device's player slot, counting from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceId(pub usize);
+
 #[derive(Debug)]
 pub enum Output {
     None,
     Unsupported,
-    Rumble { large_motor: u16, small_motor: u16 },
+    Rumble {
+        large_motor: u16,
+        small_motor: u16,
+    },
+    /// A force feedback effect other than rumble started playing.
+    Effect(PlayedEffect),
+    /// The effect that was started with this id stopped playing.
+    EffectStopped {
+        id: u32,
+    },
+    /// The strength of every effect, in `0..=0xffff`.
+    Gain(u16),
+    /// The strength of the wheel's own centering spring, in `0..=0xffff`.
+    Autocenter(u16),
+    /// The color of the light bar of a Sony controller.
+    Lightbar {
+        red: u8,
+        green: u8,
+        blue: u8,
+    },
+    /// The light bar blinks, lit for `on` and dark for `off` in turns. Both
+    /// are zero when it stops blinking.
+    LightbarBlink {
+        on: Duration,
+        off: Duration,
+    },
+    /// Which player LEDs are lit, one bit per LED with the lowest for the
+    /// leftmost. On Windows, the bit is the player the ring of the Xbox 360
+    /// controller shows.
+    PlayerLeds(u8),
+    /// A consumer lit or darkened an LED, whose `index` is one of the
+    /// `EV_LED` codes in `DeviceConfig::leds`.
+    Led {
+        index: u8,
+        on: bool,
+    },
+    /// The LED of the mute button.
+    MicLed {
+        on: bool,
+    },
+    /// The effect an adaptive trigger of a DualSense plays from now on.
+    TriggerEffect {
+        trigger: Trigger,
+        effect: TriggerEffect,
+    },
+    /// The HD rumble of a Nintendo controller, for both of its sides.
+    HdRumble {
+        left: HdRumble,
+        right: HdRumble,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidInput {
+    NotANumber,
+    OutOfRange { value: f32 },
+    AxisOutOfRange { axis: Axis, value: i32 },
+    UnsupportedAxis(Axis),
+    UnsupportedButton(Button),
+    NoMotionSensors,
+    NoTouchpad,
+    UnsupportedFinger(u8),
+    NotAMouse,
+    UnsupportedCharacter(char),
 }
 
 #[cfg(target_os = "linux")]
 #[derive(Debug)]
 pub enum Error {
     PermissionDenied,
+    DeviceLimitReached,
+    Unplugged,
+    /// The device configuration asks for something the platform cannot do.
+    Unsupported(String),
+    InvalidInput(InvalidInput),
     Internal(nix::Error),
     Unknown(String),
 }
@@ -55,6 +298,10 @@ pub enum Error {
 pub enum Error {
     VigemBusNotInstalled,
     VigemBusVersionMismatch,
+    Unplugged,
+    /// The device configuration asks for something the platform cannot do.
+    Unsupported(String),
+    InvalidInput(InvalidInput),
     Internal(vigem_client::Error),
     Unknown(String),
 }
@@ -62,6 +309,10 @@ pub enum Error {
 #[cfg(target_os = "macos")]
 #[derive(Debug)]
 pub enum Error {
+    Unplugged,
+    /// The device configuration asks for something the platform cannot do.
+    Unsupported(String),
+    InvalidInput(InvalidInput),
     Unknown(String),
 }
 
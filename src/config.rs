@@ -0,0 +1,955 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::api::{Axis, AxisInfo, Button};
+use crate::hid_descriptor::HidDescriptor;
+use crate::keyboard::{Key, Layout};
+
+const STANDARD_BUTTONS: [Button; 16] = [
+    Button::DpadDown,
+    Button::DpadUp,
+    Button::DpadLeft,
+    Button::DpadRight,
+    Button::North,
+    Button::South,
+    Button::West,
+    Button::East,
+    Button::Start,
+    Button::Select,
+    Button::TriggerLeft,
+    Button::TriggerRight,
+    Button::TriggerLeft2,
+    Button::TriggerRight2,
+    Button::ThumbStickLeft,
+    Button::ThumbStickRight,
+];
+
+const MOUSE_BUTTONS: [Button; 5] = [
+    Button::MouseLeft,
+    Button::MouseRight,
+    Button::MouseMiddle,
+    Button::MouseSide,
+    Button::MouseExtra,
+];
+
+/// The paddles sit where numbered buttons 20 to 23 are, so a device cannot
+/// have both of a pair.
+const PADDLE_NUMBERS: [(Button, u8); 4] = [
+    (Button::Paddle1, 20),
+    (Button::Paddle2, 21),
+    (Button::Paddle3, 22),
+    (Button::Paddle4, 23),
+];
+
+const HAT_AXIS_INFO: AxisInfo = AxisInfo {
+    minimum: -1,
+    maximum: 1,
+    fuzz: 0,
+    flat: 0,
+    resolution: 0,
+};
+
+/// The buttons of a DualShock 4 or a DualSense. Share or Create is
+/// `Button::Select`, Options `Button::Start` and the PS button `Button::Mode`.
+const PLAYSTATION_BUTTONS: [Button; 17] = [
+    Button::DpadDown,
+    Button::DpadUp,
+    Button::DpadLeft,
+    Button::DpadRight,
+    Button::North,
+    Button::South,
+    Button::West,
+    Button::East,
+    Button::Start,
+    Button::Select,
+    Button::TriggerLeft,
+    Button::TriggerRight,
+    Button::TriggerLeft2,
+    Button::TriggerRight2,
+    Button::ThumbStickLeft,
+    Button::ThumbStickRight,
+    Button::Mode,
+];
+
+/// The buttons of a Switch Pro Controller, by their position. The B button
+/// is `Button::South`, minus `Button::Select`, plus `Button::Start` and Home
+/// `Button::Mode`. ZL and ZR are digital, as `Button::TriggerLeft2` and
+/// `Button::TriggerRight2`.
+const SWITCH_PRO_BUTTONS: [Button; 18] = [
+    Button::DpadDown,
+    Button::DpadUp,
+    Button::DpadLeft,
+    Button::DpadRight,
+    Button::North,
+    Button::South,
+    Button::West,
+    Button::East,
+    Button::Start,
+    Button::Select,
+    Button::TriggerLeft,
+    Button::TriggerRight,
+    Button::TriggerLeft2,
+    Button::TriggerRight2,
+    Button::ThumbStickLeft,
+    Button::ThumbStickRight,
+    Button::Mode,
+    Button::Capture,
+];
+
+/// The thumb sticks of a Switch Pro Controller report 12 bits.
+const SWITCH_PRO_STICK_AXIS_INFO: AxisInfo = AxisInfo {
+    minimum: -2047,
+    maximum: 2047,
+    fuzz: 0,
+    flat: 0,
+    resolution: 0,
+};
+
+/// The thumb sticks of PlayStation controllers report a byte, centered on 128.
+const PLAYSTATION_STICK_AXIS_INFO: AxisInfo = AxisInfo {
+    minimum: -128,
+    maximum: 127,
+    fuzz: 0,
+    flat: 0,
+    resolution: 0,
+};
+
+/// An axis ranging from `-resolution` to `resolution`.
+fn centered(resolution: i32) -> AxisInfo {
+    AxisInfo {
+        minimum: -resolution,
+        maximum: resolution,
+        fuzz: 0,
+        flat: 0,
+        resolution: 0,
+    }
+}
+
+/// An axis ranging from 0 to `resolution`, like a pedal or a throttle.
+fn one_way(resolution: i32) -> AxisInfo {
+    AxisInfo {
+        minimum: 0,
+        maximum: resolution,
+        fuzz: 0,
+        flat: 0,
+        resolution: 0,
+    }
+}
+
+/// A generic joystick, such as an arcade stick, a button box or a cockpit
+/// panel.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JoystickConfig {
+    /// The joystick has `Button::Numbered(0)` up to, but not including,
+    /// `Button::Numbered(buttons)`.
+    pub buttons: u8,
+    pub axes: Vec<(Axis, AxisInfo)>,
+    /// Each hat is a pair of axes, `Axis::hat(n)`, ranging from -1 to 1.
+    pub hats: u8,
+}
+
+impl JoystickConfig {
+    pub const MAX_BUTTONS: u8 = 56;
+    pub const MAX_HATS: u8 = 4;
+
+    fn validate(&self) -> Result<(), String> {
+        let hats = (0..self.hats)
+            .filter_map(Axis::hat)
+            .flat_map(|(x, y)| vec![x, y]);
+        let mut axes = HashSet::new();
+        for axis in self.axes.iter().map(|(axis, _)| *axis).chain(hats) {
+            if !axes.insert(axis) {
+                return Err(format!("{:?} is used more than once", axis));
+            }
+        }
+        if let Some((axis, _)) = self
+            .axes
+            .iter()
+            .find(|(_, info)| info.minimum >= info.maximum)
+        {
+            return Err(format!(
+                "The minimum of {:?} has to be less than its maximum",
+                axis
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A racing wheel with pedals and a shifter, and force feedback that can
+/// express torque on the wheel.
+///
+/// The shifter and the buttons on the rim are numbered buttons. The pedals
+/// are `Axis::Gas`, `Axis::Brake` and, for the clutch, `Axis::Z`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WheelConfig {
+    /// `Axis::Wheel` or `Axis::X`. Some games only look at the latter.
+    pub steering_axis: Axis,
+    /// The steering axis ranges from `-steering_resolution` to
+    /// `steering_resolution`.
+    pub steering_resolution: i32,
+    /// The pedals range from 0, released, to `pedal_resolution`.
+    pub pedal_resolution: i32,
+    pub clutch: bool,
+    pub buttons: u8,
+}
+
+impl Default for WheelConfig {
+    fn default() -> Self {
+        Self {
+            steering_axis: Axis::Wheel,
+            steering_resolution: 32767,
+            pedal_resolution: 1023,
+            clutch: true,
+            buttons: 24,
+        }
+    }
+}
+
+/// A flight stick, or a whole HOTAS merged into one device.
+///
+/// The stick is `Axis::X` and `Axis::Y`, the twist `Axis::Rz`, the throttle
+/// `Axis::Throttle` and the rudder `Axis::Rudder`. Axes whose resolution is
+/// `None` are left out.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlightConfig {
+    /// The stick ranges from `-stick_resolution` to `stick_resolution`.
+    pub stick_resolution: i32,
+    pub twist_resolution: Option<i32>,
+    /// The throttle only goes one way, from 0 to `throttle_resolution`.
+    pub throttle_resolution: Option<i32>,
+    pub rudder_resolution: Option<i32>,
+    pub hats: u8,
+    pub buttons: u8,
+}
+
+impl Default for FlightConfig {
+    fn default() -> Self {
+        Self {
+            stick_resolution: 32767,
+            twist_resolution: Some(32767),
+            throttle_resolution: Some(65535),
+            rudder_resolution: Some(32767),
+            hats: 1,
+            buttons: 32,
+        }
+    }
+}
+
+/// Motion sensors of a device, plugged in as a node of their own next to it
+/// the way Sony and Nintendo controllers do.
+///
+/// The accelerometer is on `ABS_X`, `ABS_Y` and `ABS_Z` of that node, the
+/// gyroscope on `ABS_RX`, `ABS_RY` and `ABS_RZ`.
+///
+/// The node shares the physical path of the device but not its uniq, which
+/// uinput cannot set. Consumers that pair sensors by uniq, like SDL, do not
+/// pair it with the device, and with several devices plugged in they may
+/// pair it with the wrong one. `DeviceKind::DualShock4`,
+/// `DeviceKind::DualSense` and `DeviceKind::SwitchPro` are emulated through
+/// uhid with sensors of their own and a uniq, so they pair reliably.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MotionConfig {
+    /// Accelerometer units per g.
+    pub accel_resolution: i32,
+    /// The accelerometer measures up to this many g either way.
+    pub accel_range: i32,
+    /// Gyroscope units per degree per second.
+    pub gyro_resolution: i32,
+    /// The gyroscope measures up to this many degrees per second either way.
+    pub gyro_range: i32,
+}
+
+impl Default for MotionConfig {
+    /// The sensors of a DualSense.
+    fn default() -> Self {
+        Self {
+            accel_resolution: 8192,
+            accel_range: 4,
+            gyro_resolution: 1024,
+            gyro_range: 2048,
+        }
+    }
+}
+
+impl MotionConfig {
+    fn validate(&self) -> Result<(), String> {
+        let limits = [
+            (self.accel_resolution, self.accel_range),
+            (self.gyro_resolution, self.gyro_range),
+        ];
+        for (resolution, range) in limits.iter() {
+            if *resolution <= 0 || *range <= 0 || resolution.checked_mul(*range).is_none() {
+                return Err(format!(
+                    "Invalid motion sensor resolution {} and range {}",
+                    resolution, range
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn accel_info(&self) -> AxisInfo {
+        AxisInfo {
+            fuzz: 16,
+            resolution: self.accel_resolution,
+            ..centered(self.accel_resolution * self.accel_range)
+        }
+    }
+
+    pub(crate) fn gyro_info(&self) -> AxisInfo {
+        AxisInfo {
+            fuzz: 16,
+            resolution: self.gyro_resolution,
+            ..centered(self.gyro_resolution * self.gyro_range)
+        }
+    }
+}
+
+/// A touchpad like the one of a DualShock 4 or a DualSense, plugged in as a
+/// node of its own next to the device. Its click is reported with
+/// `Input::TouchpadClick`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TouchpadConfig {
+    /// Positions range from 0 to `width - 1` horizontally.
+    pub width: i32,
+    /// Positions range from 0 to `height - 1` vertically.
+    pub height: i32,
+    /// Units per millimeter.
+    pub resolution: i32,
+    /// How many fingers are tracked at once, at most 5.
+    pub fingers: u8,
+}
+
+impl Default for TouchpadConfig {
+    /// The touchpad of a DualShock 4.
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 942,
+            resolution: 44,
+            fingers: 2,
+        }
+    }
+}
+
+impl TouchpadConfig {
+    pub const MAX_FINGERS: u8 = 5;
+
+    fn validate(&self) -> Result<(), String> {
+        if self.width <= 1 || self.height <= 1 || self.resolution < 0 {
+            return Err(format!(
+                "Invalid touchpad size {}x{} with resolution {}",
+                self.width, self.height, self.resolution
+            ));
+        }
+        if self.fingers == 0 || self.fingers > Self::MAX_FINGERS {
+            return Err(format!(
+                "A touchpad tracks 1 to {} fingers, got {}",
+                Self::MAX_FINGERS,
+                self.fingers
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A keyboard with every key there is. Its num, caps and scroll lock LEDs,
+/// `EV_LED` codes 0 to 2, come out as `Output::Led`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyboardConfig {
+    /// The layout `type_text` types with.
+    pub layout: Layout,
+}
+
+/// A touch screen or drawing tablet, which puts the pointer where it is
+/// touched. It is touched with `Input::Touch` on finger 0 and lifted with
+/// `Input::TouchUp`. Its position is also `Axis::X` and `Axis::Y`, and its
+/// pressure `Axis::Pressure`, which is at its highest when a touch starts.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PointerConfig {
+    /// Positions range from 0 to `width - 1` horizontally.
+    pub width: i32,
+    /// Positions range from 0 to `height - 1` vertically.
+    pub height: i32,
+    /// Units per millimeter.
+    pub resolution: i32,
+    /// The highest pressure, if the device senses it.
+    pub pressure: Option<i32>,
+    /// Whether it is a drawing tablet touched with a pen rather than a touch
+    /// screen.
+    pub pen: bool,
+}
+
+impl Default for PointerConfig {
+    /// A 24 inch touch screen in full HD.
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            resolution: 4,
+            pressure: None,
+            pen: false,
+        }
+    }
+}
+
+impl PointerConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.width <= 1 || self.height <= 1 || self.resolution < 0 {
+            return Err(format!(
+                "Invalid pointer size {}x{} with resolution {}",
+                self.width, self.height, self.resolution
+            ));
+        }
+        if let Some(pressure) = self.pressure {
+            if pressure <= 0 {
+                return Err(format!("Pressure has to be positive, got {}", pressure));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What kind of device is plugged in, and which inputs it has.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceKind {
+    /// A gamepad with the standard buttons and two thumb sticks.
+    Gamepad,
+    Joystick(JoystickConfig),
+    Wheel(WheelConfig),
+    Flight(FlightConfig),
+    /// A keyboard, whose buttons are `Button::Key`.
+    Keyboard(KeyboardConfig),
+    /// A mouse with five buttons and high-resolution scroll wheels, moved
+    /// with `Input::MouseMove` and scrolled with `Input::Scroll`.
+    Mouse,
+    /// A touch screen or drawing tablet.
+    Pointer(PointerConfig),
+    /// A Sony DualShock 4, emulated as the genuine HID device so that games
+    /// recognize it. It always has motion sensors and a touchpad with two
+    /// fingers, and its triggers are also `Axis::Z` and `Axis::Rz`, from 0 to
+    /// 255. Only available on Linux, through `/dev/uhid`.
+    DualShock4,
+    /// A Sony DualSense, emulated like `DualShock4`. Games can drive its
+    /// adaptive triggers, player LEDs and mute LED, which come out as
+    /// outputs. Only available on Linux, through `/dev/uhid`.
+    DualSense,
+    /// A Nintendo Switch Pro Controller, emulated as the genuine HID device.
+    /// It always has motion sensors, and its triggers are buttons. Player
+    /// lights and HD rumble come out as outputs. Only available on Linux,
+    /// through `/dev/uhid`.
+    SwitchPro,
+    /// A generic HID device with the inputs and outputs of its descriptor.
+    /// Only available on Linux, through `/dev/uhid`.
+    Hid(HidDescriptor),
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for DeviceKind {
+    fn default() -> Self {
+        DeviceKind::Gamepad
+    }
+}
+
+impl DeviceKind {
+    /// Whether the device is emulated as a HID device rather than put
+    /// together from input events.
+    pub(crate) fn is_hid(&self) -> bool {
+        matches!(
+            self,
+            DeviceKind::DualShock4
+                | DeviceKind::DualSense
+                | DeviceKind::SwitchPro
+                | DeviceKind::Hid(_)
+        )
+    }
+}
+
+/// How a device presents itself to the system when it is plugged in.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceConfig {
+    /// Buttons the device advertises on top of the standard gamepad layout,
+    /// such as `Button::Mode`, `Button::Capture` or the back paddles. Pressing
+    /// a button that is not advertised fails with
+    /// `InvalidInput::UnsupportedButton`.
+    pub extra_buttons: Vec<Button>,
+    pub kind: DeviceKind,
+    /// Motion sensors to plug in along with the device, for
+    /// `Input::Motion`.
+    pub motion: Option<MotionConfig>,
+    /// A touchpad to plug in along with the device, for `Input::Touch` and
+    /// `Input::TouchUp`.
+    pub touchpad: Option<TouchpadConfig>,
+    /// The `EV_LED` codes the device advertises, such as 8 for `LED_MISC`.
+    /// Consumers that light or darken one of them come out as `Output::Led`.
+    pub leds: Vec<u8>,
+}
+
+impl DeviceConfig {
+    /// The highest `EV_LED` code, `LED_MAX`.
+    pub const MAX_LED: u8 = 0x0f;
+
+    pub fn joystick(joystick: JoystickConfig) -> Self {
+        Self {
+            kind: DeviceKind::Joystick(joystick),
+            ..Self::default()
+        }
+    }
+
+    pub fn wheel(wheel: WheelConfig) -> Self {
+        Self {
+            kind: DeviceKind::Wheel(wheel),
+            ..Self::default()
+        }
+    }
+
+    pub fn flight(flight: FlightConfig) -> Self {
+        Self {
+            kind: DeviceKind::Flight(flight),
+            ..Self::default()
+        }
+    }
+
+    pub fn hid(descriptor: HidDescriptor) -> Self {
+        Self {
+            kind: DeviceKind::Hid(descriptor),
+            ..Self::default()
+        }
+    }
+
+    /// Checks the limits that hold on every platform.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let (buttons, hats) = match &self.kind {
+            DeviceKind::Gamepad => (0, 0),
+            DeviceKind::Joystick(joystick) => {
+                joystick.validate()?;
+                (joystick.buttons, joystick.hats)
+            }
+            DeviceKind::Wheel(wheel) => {
+                if !matches!(wheel.steering_axis, Axis::Wheel | Axis::X) {
+                    return Err(format!(
+                        "A wheel steers with `Axis::Wheel` or `Axis::X`, got {:?}",
+                        wheel.steering_axis
+                    ));
+                }
+                if wheel.steering_resolution <= 0 || wheel.pedal_resolution <= 0 {
+                    return Err("Wheel resolutions have to be positive".to_string());
+                }
+                (wheel.buttons, 0)
+            }
+            DeviceKind::Flight(flight) => {
+                let resolutions = [
+                    Some(flight.stick_resolution),
+                    flight.twist_resolution,
+                    flight.throttle_resolution,
+                    flight.rudder_resolution,
+                ];
+                if resolutions.iter().flatten().any(|r| *r <= 0) {
+                    return Err("Flight stick resolutions have to be positive".to_string());
+                }
+                (flight.buttons, flight.hats)
+            }
+            DeviceKind::Keyboard(_) | DeviceKind::Mouse => (0, 0),
+            DeviceKind::Pointer(pointer) => {
+                if self.touchpad.is_some() {
+                    return Err("A pointer device is a touch surface of its own".to_string());
+                }
+                pointer.validate()?;
+                (0, 0)
+            }
+            DeviceKind::DualShock4 | DeviceKind::DualSense => {
+                if self.motion.is_some() || self.touchpad.is_some() {
+                    return Err(
+                        "A PlayStation controller has motion sensors and a touchpad of its own"
+                            .to_string(),
+                    );
+                }
+                if let Some(button) = self
+                    .extra_buttons
+                    .iter()
+                    .find(|button| !PLAYSTATION_BUTTONS.contains(button))
+                {
+                    return Err(format!(
+                        "A PlayStation controller has no {:?} button",
+                        button
+                    ));
+                }
+                (0, 0)
+            }
+            DeviceKind::SwitchPro => {
+                if self.motion.is_some() || self.touchpad.is_some() {
+                    return Err(
+                        "A Switch Pro Controller has motion sensors of its own and no touchpad"
+                            .to_string(),
+                    );
+                }
+                if let Some(button) = self
+                    .extra_buttons
+                    .iter()
+                    .find(|button| !SWITCH_PRO_BUTTONS.contains(button))
+                {
+                    return Err(format!(
+                        "A Switch Pro Controller has no {:?} button",
+                        button
+                    ));
+                }
+                (0, 0)
+            }
+            DeviceKind::Hid(descriptor) => {
+                if !self.extra_buttons.is_empty() {
+                    return Err("The buttons of a HID device are in its descriptor".to_string());
+                }
+                descriptor.validate()?;
+                (descriptor.button_count(), descriptor.hat_count())
+            }
+        };
+        if buttons > JoystickConfig::MAX_BUTTONS {
+            return Err(format!(
+                "A device can have at most {} numbered buttons, got {}",
+                JoystickConfig::MAX_BUTTONS,
+                buttons
+            ));
+        }
+        if hats > JoystickConfig::MAX_HATS {
+            return Err(format!(
+                "A device can have at most {} hats, got {}",
+                JoystickConfig::MAX_HATS,
+                hats
+            ));
+        }
+        if let Some(motion) = &self.motion {
+            motion.validate()?;
+        }
+        if let Some(touchpad) = &self.touchpad {
+            touchpad.validate()?;
+        }
+        if !self.leds.is_empty() && self.kind.is_hid() {
+            return Err("The LEDs of a HID device are its own".to_string());
+        }
+        if let Some(led) = self.leds.iter().find(|led| **led > Self::MAX_LED) {
+            return Err(format!("There is no LED {}", led));
+        }
+        for button in self.extra_buttons.iter() {
+            match button {
+                Button::Numbered(n) if *n >= JoystickConfig::MAX_BUTTONS => {
+                    return Err(format!("There is no button {}", n));
+                }
+                Button::Key(key) if !key.is_key() => {
+                    return Err(format!("There is no key {}", key.0));
+                }
+                _ => {}
+            }
+        }
+        if !self.kind.is_hid() {
+            let buttons = self.buttons();
+            for (paddle, n) in PADDLE_NUMBERS.iter() {
+                if buttons.contains(paddle) && buttons.contains(&Button::Numbered(*n)) {
+                    return Err(format!(
+                        "{:?} and button {} cannot be on the same device",
+                        paddle, n
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn buttons(&self) -> HashSet<Button> {
+        let buttons: Vec<Button> = match &self.kind {
+            DeviceKind::Gamepad => STANDARD_BUTTONS.to_vec(),
+            DeviceKind::Joystick(joystick) => (0..joystick.buttons).map(Button::Numbered).collect(),
+            DeviceKind::Wheel(wheel) => (0..wheel.buttons).map(Button::Numbered).collect(),
+            DeviceKind::Flight(flight) => (0..flight.buttons).map(Button::Numbered).collect(),
+            DeviceKind::Keyboard(_) => Key::all().map(Button::Key).collect(),
+            DeviceKind::Mouse => MOUSE_BUTTONS.to_vec(),
+            DeviceKind::Pointer(_) => Vec::new(),
+            DeviceKind::DualShock4 | DeviceKind::DualSense => PLAYSTATION_BUTTONS.to_vec(),
+            DeviceKind::SwitchPro => SWITCH_PRO_BUTTONS.to_vec(),
+            DeviceKind::Hid(descriptor) => (0..descriptor.button_count())
+                .map(Button::Numbered)
+                .collect(),
+        };
+        buttons
+            .into_iter()
+            .chain(self.extra_buttons.iter().copied())
+            .collect()
+    }
+
+    /// The axes of the device. Gamepads get their thumb sticks with
+    /// `thumb_stick`, the range of the platform.
+    pub(crate) fn axes(&self, thumb_stick: AxisInfo) -> HashMap<Axis, AxisInfo> {
+        match &self.kind {
+            DeviceKind::Gamepad => [Axis::X, Axis::Y, Axis::Rx, Axis::Ry]
+                .iter()
+                .map(|axis| (*axis, thumb_stick))
+                .collect(),
+            DeviceKind::Joystick(joystick) => {
+                let hats = (0..joystick.hats)
+                    .filter_map(Axis::hat)
+                    .flat_map(|(x, y)| vec![(x, HAT_AXIS_INFO), (y, HAT_AXIS_INFO)]);
+                joystick.axes.iter().copied().chain(hats).collect()
+            }
+            DeviceKind::Wheel(wheel) => {
+                let steering = centered(wheel.steering_resolution);
+                let pedal = one_way(wheel.pedal_resolution);
+
+                let mut axes = HashMap::new();
+                axes.insert(wheel.steering_axis, steering);
+                axes.insert(Axis::Gas, pedal);
+                axes.insert(Axis::Brake, pedal);
+                if wheel.clutch {
+                    axes.insert(Axis::Z, pedal);
+                }
+                axes
+            }
+            DeviceKind::Flight(flight) => {
+                let mut axes = HashMap::new();
+                axes.insert(Axis::X, centered(flight.stick_resolution));
+                axes.insert(Axis::Y, centered(flight.stick_resolution));
+                if let Some(resolution) = flight.twist_resolution {
+                    axes.insert(Axis::Rz, centered(resolution));
+                }
+                if let Some(resolution) = flight.throttle_resolution {
+                    axes.insert(Axis::Throttle, one_way(resolution));
+                }
+                if let Some(resolution) = flight.rudder_resolution {
+                    axes.insert(Axis::Rudder, centered(resolution));
+                }
+                for (x, y) in (0..flight.hats).filter_map(Axis::hat) {
+                    axes.insert(x, HAT_AXIS_INFO);
+                    axes.insert(y, HAT_AXIS_INFO);
+                }
+                axes
+            }
+            DeviceKind::Keyboard(_) | DeviceKind::Mouse => HashMap::new(),
+            DeviceKind::Pointer(pointer) => {
+                let position = |size: i32| AxisInfo {
+                    resolution: pointer.resolution,
+                    ..one_way(size - 1)
+                };
+
+                let mut axes = HashMap::new();
+                axes.insert(Axis::X, position(pointer.width));
+                axes.insert(Axis::Y, position(pointer.height));
+                if let Some(pressure) = pointer.pressure {
+                    axes.insert(Axis::Pressure, one_way(pressure));
+                }
+                axes
+            }
+            DeviceKind::DualShock4 | DeviceKind::DualSense => {
+                let sticks = [Axis::X, Axis::Y, Axis::Rx, Axis::Ry]
+                    .iter()
+                    .map(|axis| (*axis, PLAYSTATION_STICK_AXIS_INFO));
+                let triggers = [Axis::Z, Axis::Rz].iter().map(|axis| (*axis, one_way(255)));
+                sticks.chain(triggers).collect()
+            }
+            DeviceKind::SwitchPro => [Axis::X, Axis::Y, Axis::Rx, Axis::Ry]
+                .iter()
+                .map(|axis| (*axis, SWITCH_PRO_STICK_AXIS_INFO))
+                .collect(),
+            DeviceKind::Hid(descriptor) => {
+                let hats = (0..descriptor.hat_count())
+                    .filter_map(Axis::hat)
+                    .flat_map(|(x, y)| vec![(x, HAT_AXIS_INFO), (y, HAT_AXIS_INFO)]);
+                descriptor.axes().iter().copied().chain(hats).collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joystick_has_numbered_buttons_and_hat_axes() {
+        let config = DeviceConfig::joystick(JoystickConfig {
+            buttons: 3,
+            axes: vec![(
+                Axis::Throttle,
+                AxisInfo {
+                    minimum: 0,
+                    maximum: 1023,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                },
+            )],
+            hats: 2,
+        });
+
+        let buttons = config.buttons();
+        assert_eq!(buttons.len(), 3);
+        assert!(buttons.contains(&Button::Numbered(2)));
+
+        let axes = config.axes(HAT_AXIS_INFO);
+        assert_eq!(axes.len(), 5);
+        assert_eq!(axes[&Axis::Hat1Y], HAT_AXIS_INFO);
+        assert_eq!(axes[&Axis::Throttle].maximum, 1023);
+    }
+
+    #[test]
+    fn flight_stick_leaves_out_missing_axes() {
+        let config = DeviceConfig::flight(FlightConfig {
+            twist_resolution: None,
+            hats: 4,
+            ..FlightConfig::default()
+        });
+
+        let axes = config.axes(HAT_AXIS_INFO);
+        assert!(!axes.contains_key(&Axis::Rz));
+        assert_eq!(axes[&Axis::Throttle], one_way(65535));
+        assert_eq!(axes[&Axis::Rudder], centered(32767));
+        assert!(axes.contains_key(&Axis::Hat3Y));
+    }
+
+    #[test]
+    fn dualshock4_has_a_fixed_layout() {
+        let config = DeviceConfig {
+            kind: DeviceKind::DualShock4,
+            ..DeviceConfig::default()
+        };
+        assert!(config.buttons().contains(&Button::Mode));
+        assert_eq!(config.axes(HAT_AXIS_INFO)[&Axis::Rz], one_way(255));
+
+        let with_paddle = DeviceConfig {
+            extra_buttons: vec![Button::Paddle1],
+            ..config.clone()
+        };
+        assert!(with_paddle.validate().is_err());
+
+        let with_motion = DeviceConfig {
+            motion: Some(MotionConfig::default()),
+            ..config
+        };
+        assert!(with_motion.validate().is_err());
+    }
+
+    #[test]
+    fn switch_pro_has_digital_triggers() {
+        let config = DeviceConfig {
+            kind: DeviceKind::SwitchPro,
+            ..DeviceConfig::default()
+        };
+        assert!(config.buttons().contains(&Button::Capture));
+        assert!(!config.axes(HAT_AXIS_INFO).contains_key(&Axis::Z));
+
+        let with_touchpad = DeviceConfig {
+            touchpad: Some(TouchpadConfig::default()),
+            ..config
+        };
+        assert!(with_touchpad.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_too_many_buttons() {
+        let config = DeviceConfig::joystick(JoystickConfig {
+            buttons: JoystickConfig::MAX_BUTTONS + 1,
+            ..JoystickConfig::default()
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_paddles_on_numbered_buttons() {
+        let mut config = DeviceConfig::joystick(JoystickConfig {
+            buttons: 21,
+            ..JoystickConfig::default()
+        });
+        config.extra_buttons = vec![Button::Paddle2];
+        assert!(config.validate().is_ok());
+
+        config.extra_buttons = vec![Button::Paddle1];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_bad_joystick_axes() {
+        let info = one_way(255);
+        let mut joystick = JoystickConfig {
+            axes: vec![(Axis::Z, info), (Axis::Rz, info)],
+            hats: 1,
+            ..JoystickConfig::default()
+        };
+        assert!(DeviceConfig::joystick(joystick.clone()).validate().is_ok());
+
+        joystick.axes.push((Axis::Hat0Y, HAT_AXIS_INFO));
+        assert!(DeviceConfig::joystick(joystick.clone()).validate().is_err());
+
+        joystick.axes = vec![(Axis::Z, info), (Axis::Z, info)];
+        assert!(DeviceConfig::joystick(joystick.clone()).validate().is_err());
+
+        joystick.axes = vec![(Axis::Z, one_way(0))];
+        assert!(DeviceConfig::joystick(joystick).validate().is_err());
+    }
+
+    #[test]
+    fn keyboard_has_every_key_and_no_axes() {
+        let config = DeviceConfig {
+            kind: DeviceKind::Keyboard(KeyboardConfig::default()),
+            ..DeviceConfig::default()
+        };
+        assert!(config.buttons().contains(&Button::Key(Key::ENTER)));
+        assert!(!config.buttons().contains(&Button::South));
+        assert!(config.axes(HAT_AXIS_INFO).is_empty());
+
+        let with_button = DeviceConfig {
+            extra_buttons: vec![Button::Key(Key(0x130))],
+            ..config
+        };
+        assert!(with_button.validate().is_err());
+    }
+
+    #[test]
+    fn pointer_has_a_position_and_pressure() {
+        let config = DeviceConfig {
+            kind: DeviceKind::Pointer(PointerConfig {
+                pressure: Some(1023),
+                ..PointerConfig::default()
+            }),
+            ..DeviceConfig::default()
+        };
+        assert!(config.buttons().is_empty());
+
+        let axes = config.axes(HAT_AXIS_INFO);
+        assert_eq!(axes[&Axis::X].maximum, 1919);
+        assert_eq!(axes[&Axis::Y].resolution, 4);
+        assert_eq!(axes[&Axis::Pressure], one_way(1023));
+
+        let with_touchpad = DeviceConfig {
+            touchpad: Some(TouchpadConfig::default()),
+            ..config
+        };
+        assert!(with_touchpad.validate().is_err());
+    }
+
+    #[test]
+    fn leds_are_only_advertised_by_input_devices() {
+        let config = DeviceConfig {
+            leds: vec![0x08],
+            ..DeviceConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let past_max = DeviceConfig {
+            leds: vec![DeviceConfig::MAX_LED + 1],
+            ..DeviceConfig::default()
+        };
+        assert!(past_max.validate().is_err());
+
+        let dualsense = DeviceConfig {
+            kind: DeviceKind::DualSense,
+            ..config
+        };
+        assert!(dualsense.validate().is_err());
+    }
+}
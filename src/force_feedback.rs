@@ -0,0 +1,316 @@
+use std::time::Duration;
+
+/// Fades the start and the end of an effect in and out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Envelope {
+    pub attack_length: Duration,
+    /// Magnitude at the very start of the effect, in `0..=0x7fff`.
+    pub attack_level: u16,
+    pub fade_length: Duration,
+    /// Magnitude at the very end of the effect, in `0..=0x7fff`.
+    pub fade_level: u16,
+}
+
+impl Envelope {
+    /// The level of the effect at `position`, with `level` as its magnitude
+    /// between the attack and the fade. `length` is `None` for effects that
+    /// play until stopped, which never fade.
+    fn apply(&self, level: i16, position: Duration, length: Option<Duration>) -> f32 {
+        let magnitude = (level as f32).abs();
+        let sign = (level as f32).signum();
+
+        let magnitude = if position < self.attack_length {
+            let progress = position.as_secs_f32() / self.attack_length.as_secs_f32();
+            self.attack_level as f32 + (magnitude - self.attack_level as f32) * progress
+        } else {
+            match length {
+                Some(length) if length.saturating_sub(position) < self.fade_length => {
+                    let remaining = length.saturating_sub(position).as_secs_f32();
+                    let progress = 1.0 - remaining / self.fade_length.as_secs_f32();
+                    magnitude + (self.fade_level as f32 - magnitude) * progress
+                }
+                _ => magnitude,
+            }
+        };
+
+        sign * magnitude
+    }
+}
+
+/// Parameters of an effect whose force depends on the position or the
+/// velocity of the axis, like a spring or a damper.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Condition {
+    pub right_saturation: u16,
+    pub left_saturation: u16,
+    pub right_coefficient: i16,
+    pub left_coefficient: i16,
+    /// Size of the dead zone around `center` where there is no force.
+    pub deadband: u16,
+    pub center: i16,
+}
+
+/// A force feedback effect uploaded by a consumer of the device.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Rumble {
+        large_motor: u16,
+        small_motor: u16,
+    },
+    /// A force of a constant `level` in `-0x7fff..=0x7fff`.
+    Constant {
+        level: i16,
+        envelope: Envelope,
+    },
+    /// Pulls the axis towards the center, the further away the stronger.
+    Spring(Condition),
+    /// Resists movement of the axis, the faster the stronger.
+    Damper(Condition),
+}
+
+/// An effect that a consumer started playing, with its timing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayedEffect {
+    /// The id the effect was uploaded with. Stopping it reports the same id.
+    pub id: u32,
+    pub effect: Effect,
+    /// Direction of the force in `0..=0xffff`, counter-clockwise from down.
+    /// `0x4000` pushes to the left and `0xc000` to the right.
+    pub direction: u16,
+    pub delay: Duration,
+    /// How long one run of the effect lasts, `None` if it plays until stopped.
+    pub length: Option<Duration>,
+    /// How many times in a row the effect is played.
+    pub count: u32,
+}
+
+impl PlayedEffect {
+    /// The torque the effect puts on a steering wheel `elapsed` after it was
+    /// started, in `-1.0..=1.0` where positive values turn to the right.
+    ///
+    /// Returns `None` for effects whose force depends on the position of the
+    /// wheel, like springs and dampers, which the caller has to simulate with
+    /// the effect's condition.
+    pub fn torque_at(&self, elapsed: Duration) -> Option<f32> {
+        let (level, envelope) = match &self.effect {
+            Effect::Constant { level, envelope } => (*level, envelope),
+            _ => return None,
+        };
+
+        let elapsed = match elapsed.checked_sub(self.delay) {
+            Some(elapsed) => elapsed,
+            None => return Some(0.0),
+        };
+        let position = match self.length {
+            Some(length) if length == Duration::from_secs(0) => return Some(0.0),
+            Some(length) => {
+                // Integer nanoseconds, as floats could round `elapsed` into the
+                // next run right before it starts.
+                let (elapsed, length) = (elapsed.as_nanos(), length.as_nanos());
+                if elapsed / length >= u128::from(self.count.max(1)) {
+                    return Some(0.0);
+                }
+                Duration::from_nanos((elapsed % length) as u64)
+            }
+            None => elapsed,
+        };
+
+        let level = envelope.apply(level, position, self.length) / i16::MAX as f32;
+        let angle = self.direction as f32 / 0x10000 as f32 * std::f32::consts::TAU;
+        Some((-angle.sin() * level).clamp(-1.0, 1.0))
+    }
+}
+
+/// What an adaptive trigger effect does, as far as it is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerEffectKind {
+    Off,
+    /// Resists pulling the trigger, with a force per zone of its travel.
+    Feedback,
+    /// Resists until a point, then gives way like a gun's trigger.
+    Weapon,
+    /// Vibrates from a point of the travel on.
+    Vibration,
+    Bow,
+    Galloping,
+    Machine,
+    /// The older effects, with fewer parameters.
+    SimpleFeedback,
+    SimpleWeapon,
+    SimpleVibration,
+    LimitedFeedback,
+    LimitedWeapon,
+    Calibration,
+    Unknown,
+}
+
+/// An adaptive trigger effect of a DualSense as the game sent it. `mode` and
+/// `parameters` are the raw bytes, so that the effect can be replayed on a
+/// real controller as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerEffect {
+    pub mode: u8,
+    pub parameters: [u8; 10],
+}
+
+impl TriggerEffect {
+    pub fn kind(&self) -> TriggerEffectKind {
+        match self.mode {
+            0x00 | 0x05 => TriggerEffectKind::Off,
+            0x01 => TriggerEffectKind::SimpleFeedback,
+            0x02 => TriggerEffectKind::SimpleWeapon,
+            0x06 => TriggerEffectKind::SimpleVibration,
+            0x11 => TriggerEffectKind::LimitedFeedback,
+            0x12 => TriggerEffectKind::LimitedWeapon,
+            0x21 => TriggerEffectKind::Feedback,
+            0x22 => TriggerEffectKind::Bow,
+            0x23 => TriggerEffectKind::Galloping,
+            0x25 => TriggerEffectKind::Weapon,
+            0x26 => TriggerEffectKind::Vibration,
+            0x27 => TriggerEffectKind::Machine,
+            0xfc => TriggerEffectKind::Calibration,
+            _ => TriggerEffectKind::Unknown,
+        }
+    }
+}
+
+/// The HD rumble of one side of a Nintendo controller as the game sent it, a
+/// high and a low frequency band that each have an amplitude. `raw` is kept
+/// so that the rumble can be replayed on a real controller as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HdRumble {
+    pub raw: [u8; 4],
+}
+
+/// The amplitude, from 0 to 1, that a band's encoded amplitude stands for.
+/// The encoding is logarithmic, with a steeper curve for weak amplitudes.
+fn hd_rumble_amplitude(encoded: u8) -> f32 {
+    let encoded = encoded as f32;
+    let amplitude = if encoded >= 32.0 {
+        (encoded / 32.0).exp2() / 8.7
+    } else if encoded >= 16.0 {
+        (encoded / 16.0).exp2() / 17.0
+    } else {
+        encoded / 16.0 * 2.0 / 17.0
+    };
+    amplitude.min(1.0)
+}
+
+impl HdRumble {
+    /// The frequency in Hz and the amplitude of the high band.
+    pub fn high_band(&self) -> (f32, f32) {
+        let frequency = (self.raw[0] as u16 | (self.raw[1] as u16 & 0x01) << 8) / 4 + 0x60;
+        (
+            10.0 * (frequency as f32 / 32.0).exp2(),
+            hd_rumble_amplitude(self.raw[1] >> 1),
+        )
+    }
+
+    /// The frequency in Hz and the amplitude of the low band.
+    pub fn low_band(&self) -> (f32, f32) {
+        let frequency = (self.raw[2] & 0x7f) + 0x40;
+        let amplitude = self.raw[3].saturating_sub(0x40).saturating_mul(2) | self.raw[2] >> 7;
+        (
+            10.0 * (frequency as f32 / 32.0).exp2(),
+            hd_rumble_amplitude(amplitude),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(level: i16, direction: u16, envelope: Envelope) -> PlayedEffect {
+        PlayedEffect {
+            id: 0,
+            effect: Effect::Constant { level, envelope },
+            direction,
+            delay: Duration::from_millis(10),
+            length: Some(Duration::from_millis(100)),
+            count: 1,
+        }
+    }
+
+    #[test]
+    fn constant_force_follows_the_direction() {
+        let right = constant(i16::MAX, 0xc000, Envelope::default());
+        assert_eq!(right.torque_at(Duration::from_millis(5)), Some(0.0));
+        assert!((right.torque_at(Duration::from_millis(50)).unwrap() - 1.0).abs() < 1e-3);
+        assert_eq!(right.torque_at(Duration::from_millis(200)), Some(0.0));
+
+        let left = constant(i16::MAX / 2, 0x4000, Envelope::default());
+        assert!((left.torque_at(Duration::from_millis(50)).unwrap() + 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn repeats_up_to_the_last_nanosecond() {
+        let effect = PlayedEffect {
+            count: 3,
+            ..constant(i16::MAX, 0xc000, Envelope::default())
+        };
+        let end = effect.delay + effect.length.unwrap() * 3;
+        let one_ns = Duration::from_nanos(1);
+
+        for run in 1..3 {
+            let torque = effect.torque_at(effect.delay + effect.length.unwrap() * run - one_ns);
+            assert!((torque.unwrap() - 1.0).abs() < 1e-3);
+        }
+        assert!((effect.torque_at(end - one_ns).unwrap() - 1.0).abs() < 1e-3);
+        assert_eq!(effect.torque_at(end), Some(0.0));
+    }
+
+    #[test]
+    fn envelope_ramps_the_level() {
+        let effect = constant(
+            i16::MAX,
+            0xc000,
+            Envelope {
+                attack_length: Duration::from_millis(20),
+                attack_level: 0,
+                fade_length: Duration::from_millis(20),
+                fade_level: 0,
+            },
+        );
+
+        let torque = |ms| effect.torque_at(Duration::from_millis(ms)).unwrap();
+        assert!((torque(20) - 0.5).abs() < 1e-3);
+        assert!((torque(60) - 1.0).abs() < 1e-3);
+        assert!((torque(100) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn conditions_have_no_torque_of_their_own() {
+        let effect = PlayedEffect {
+            effect: Effect::Spring(Condition::default()),
+            ..constant(0, 0, Envelope::default())
+        };
+        assert_eq!(effect.torque_at(Duration::from_millis(50)), None);
+    }
+
+    #[test]
+    fn trigger_effect_modes() {
+        let effect = |mode| TriggerEffect {
+            mode,
+            parameters: [0; 10],
+        };
+        assert_eq!(effect(0x05).kind(), TriggerEffectKind::Off);
+        assert_eq!(effect(0x26).kind(), TriggerEffectKind::Vibration);
+        assert_eq!(effect(0x42).kind(), TriggerEffectKind::Unknown);
+    }
+
+    #[test]
+    fn decodes_hd_rumble() {
+        let neutral = HdRumble {
+            raw: [0x00, 0x01, 0x40, 0x40],
+        };
+        assert_eq!(neutral.high_band(), (320.0, 0.0));
+        assert_eq!(neutral.low_band(), (160.0, 0.0));
+
+        let full = HdRumble {
+            raw: [0x00, 0xc9, 0x40, 0x72],
+        };
+        assert_eq!(full.high_band().1, 1.0);
+        assert_eq!(full.low_band().1, 1.0);
+    }
+}
@@ -0,0 +1,524 @@
+use std::{collections::HashSet, convert::TryFrom};
+
+use crate::api::{Axis, AxisInfo, Output};
+
+const INPUT_REPORT: u8 = 0x01;
+const OUTPUT_REPORT: u8 = 0x02;
+
+// Usage pages.
+const GENERIC_DESKTOP: u16 = 0x01;
+const SIMULATION: u16 = 0x02;
+const GENERIC_DEVICE: u16 = 0x06;
+const LED: u16 = 0x08;
+const BUTTON: u16 = 0x09;
+const CONSUMER: u16 = 0x0c;
+const DIGITIZER: u16 = 0x0d;
+const VENDOR: u16 = 0xff00;
+
+// Short item prefixes, without their size.
+const USAGE_PAGE: u8 = 0x04;
+const USAGE: u8 = 0x08;
+const USAGE_MINIMUM: u8 = 0x18;
+const USAGE_MAXIMUM: u8 = 0x28;
+const LOGICAL_MINIMUM: u8 = 0x14;
+const LOGICAL_MAXIMUM: u8 = 0x24;
+const PHYSICAL_MINIMUM: u8 = 0x34;
+const PHYSICAL_MAXIMUM: u8 = 0x44;
+const UNIT: u8 = 0x64;
+const REPORT_SIZE: u8 = 0x74;
+const REPORT_ID: u8 = 0x84;
+const REPORT_COUNT: u8 = 0x94;
+const INPUT: u8 = 0x80;
+const OUTPUT: u8 = 0x90;
+const COLLECTION: u8 = 0xa0;
+const END_COLLECTION: u8 = 0xc0;
+
+// Main item flags.
+const CONSTANT: u32 = 0x01;
+const VARIABLE: u32 = 0x02;
+const NULL_STATE: u32 = 0x40;
+
+/// The usage page and usage an axis is reported with, if it has one.
+fn axis_usage(axis: Axis) -> Option<(u16, u16)> {
+    let usage = match axis {
+        Axis::X => (GENERIC_DESKTOP, 0x30),
+        Axis::Y => (GENERIC_DESKTOP, 0x31),
+        Axis::Z => (GENERIC_DESKTOP, 0x32),
+        Axis::Rx => (GENERIC_DESKTOP, 0x33),
+        Axis::Ry => (GENERIC_DESKTOP, 0x34),
+        Axis::Rz => (GENERIC_DESKTOP, 0x35),
+        Axis::Misc => (GENERIC_DESKTOP, 0x37),
+        Axis::Rudder => (SIMULATION, 0xba),
+        Axis::Throttle => (SIMULATION, 0xbb),
+        Axis::Gas => (SIMULATION, 0xc4),
+        Axis::Brake => (SIMULATION, 0xc5),
+        Axis::Wheel => (SIMULATION, 0xc8),
+        Axis::Volume => (CONSUMER, 0xe0),
+        Axis::Pressure => (DIGITIZER, 0x30),
+        Axis::TiltX => (DIGITIZER, 0x3d),
+        Axis::TiltY => (DIGITIZER, 0x3e),
+        Axis::ToolWidth => (DIGITIZER, 0x48),
+        _ => return None,
+    };
+    Some(usage)
+}
+
+/// How many bits an axis with `info` takes in a report.
+fn axis_size(info: &AxisInfo) -> u32 {
+    let fits_signed = info.minimum >= i16::MIN as i32 && info.maximum <= i16::MAX as i32;
+    let fits_unsigned = info.minimum >= 0 && info.maximum <= u16::MAX as i32;
+    if fits_signed || fits_unsigned {
+        16
+    } else {
+        32
+    }
+}
+
+/// Appends a short item, with the smallest size that holds `data`.
+fn unsigned_item(bytes: &mut Vec<u8>, prefix: u8, data: u32) {
+    let size = if data <= 0xff {
+        1
+    } else if data <= 0xffff {
+        2
+    } else {
+        4
+    };
+    put_item(bytes, prefix, data, size);
+}
+
+fn signed_item(bytes: &mut Vec<u8>, prefix: u8, data: i32) {
+    let size = if i8::try_from(data).is_ok() {
+        1
+    } else if i16::try_from(data).is_ok() {
+        2
+    } else {
+        4
+    };
+    put_item(bytes, prefix, data as u32, size);
+}
+
+fn put_item(bytes: &mut Vec<u8>, prefix: u8, data: u32, size: usize) {
+    let size_code = if size == 4 { 3 } else { size as u8 };
+    bytes.push(prefix | size_code);
+    bytes.extend_from_slice(&data.to_le_bytes()[..size]);
+}
+
+/// Fields of a report, packed from the lowest bit up.
+struct Bits {
+    bytes: Vec<u8>,
+    bit: usize,
+}
+
+impl Bits {
+    fn new(report_id: u8) -> Self {
+        Self {
+            bytes: vec![report_id],
+            bit: 8,
+        }
+    }
+
+    fn put(&mut self, value: u32, size: u32) {
+        for i in 0..size {
+            if self.bit == self.bytes.len() * 8 {
+                self.bytes.push(0);
+            }
+            if value >> i & 1 != 0 {
+                *self.bytes.last_mut().unwrap() |= 1 << (self.bit % 8);
+            }
+            self.bit += 1;
+        }
+    }
+
+    /// Skips to the next byte.
+    fn pad(&mut self) {
+        self.bit = self.bytes.len() * 8;
+    }
+}
+
+/// The direction of a hat as a HID hat switch, clockwise from north, or 8 when
+/// it is centered.
+fn hat_direction(x: i32, y: i32) -> u32 {
+    match (x.signum(), y.signum()) {
+        (0, -1) => 0,
+        (1, -1) => 1,
+        (1, 0) => 2,
+        (1, 1) => 3,
+        (0, 1) => 4,
+        (-1, 1) => 5,
+        (-1, 0) => 6,
+        (-1, -1) => 7,
+        _ => 8,
+    }
+}
+
+/// The inputs and outputs of a generic HID device, from which its report
+/// descriptor is made. Drivers such as `hid-generic` and consumers of hidraw,
+/// like SDL or WebHID, see the device as it is described.
+///
+/// Buttons are `Button::Numbered`, hats are pairs of axes like on a joystick,
+/// and the inputs are sent in a single input report, along with the battery
+/// strength. Rumble and LEDs come in an output report.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HidDescriptor {
+    buttons: u8,
+    axes: Vec<(Axis, AxisInfo)>,
+    hats: u8,
+    rumble: bool,
+    leds: u8,
+    battery: bool,
+}
+
+/// The state of the inputs of a generic HID device, in the order of its
+/// descriptor.
+pub(crate) struct HidState {
+    pub buttons: Vec<bool>,
+    pub hats: Vec<(i32, i32)>,
+    pub axes: Vec<i32>,
+    /// The battery strength in percent, if the device has a battery.
+    pub battery: u8,
+}
+
+impl HidDescriptor {
+    pub const MAX_LEDS: u8 = 8;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `Button::Numbered(0)` up to, but not including,
+    /// `Button::Numbered(buttons)`.
+    pub fn buttons(mut self, buttons: u8) -> Self {
+        self.buttons = buttons;
+        self
+    }
+
+    /// Adds `axis` with the range of `info`. Hat axes are added with `hats`
+    /// instead.
+    pub fn axis(mut self, axis: Axis, info: AxisInfo) -> Self {
+        self.axes.push((axis, info));
+        self
+    }
+
+    /// Hat switches, which are set through their pairs of axes,
+    /// `Axis::hat(n)`.
+    pub fn hats(mut self, hats: u8) -> Self {
+        self.hats = hats;
+        self
+    }
+
+    /// A strong and a weak motor, which come out as `Output::Rumble`.
+    pub fn rumble(mut self) -> Self {
+        self.rumble = true;
+        self
+    }
+
+    /// Indicator LEDs, which come out as `Output::PlayerLeds`.
+    pub fn leds(mut self, leds: u8) -> Self {
+        self.leds = leds;
+        self
+    }
+
+    /// A battery strength the system can read. Whether the battery charges
+    /// cannot be told.
+    pub fn battery(mut self) -> Self {
+        self.battery = true;
+        self
+    }
+
+    pub(crate) fn button_count(&self) -> u8 {
+        self.buttons
+    }
+
+    pub(crate) fn hat_count(&self) -> u8 {
+        self.hats
+    }
+
+    pub(crate) fn axes(&self) -> &[(Axis, AxisInfo)] {
+        &self.axes
+    }
+
+    pub(crate) fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        for (axis, info) in self.axes.iter() {
+            if axis_usage(*axis).is_none() {
+                return Err(format!("{:?} cannot be described as a HID usage", axis));
+            }
+            if !seen.insert(*axis) {
+                return Err(format!("{:?} is described more than once", axis));
+            }
+            if info.minimum >= info.maximum {
+                return Err(format!(
+                    "Invalid range {}..={} for {:?}",
+                    info.minimum, info.maximum, axis
+                ));
+            }
+        }
+        if self.leds > Self::MAX_LEDS {
+            return Err(format!(
+                "A HID device can have at most {} LEDs, got {}",
+                Self::MAX_LEDS,
+                self.leds
+            ));
+        }
+        Ok(())
+    }
+
+    /// The report descriptor.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let items = &mut bytes;
+
+        unsigned_item(items, USAGE_PAGE, GENERIC_DESKTOP as u32);
+        // A joystick.
+        unsigned_item(items, USAGE, 0x04);
+        // An application collection.
+        unsigned_item(items, COLLECTION, 0x01);
+        unsigned_item(items, REPORT_ID, INPUT_REPORT as u32);
+
+        if self.buttons > 0 {
+            unsigned_item(items, USAGE_PAGE, BUTTON as u32);
+            unsigned_item(items, USAGE_MINIMUM, 1);
+            unsigned_item(items, USAGE_MAXIMUM, self.buttons as u32);
+            signed_item(items, LOGICAL_MINIMUM, 0);
+            signed_item(items, LOGICAL_MAXIMUM, 1);
+            unsigned_item(items, REPORT_SIZE, 1);
+            unsigned_item(items, REPORT_COUNT, self.buttons as u32);
+            unsigned_item(items, INPUT, VARIABLE);
+            padding(items, INPUT, self.buttons as u32);
+        }
+
+        if self.hats > 0 {
+            unsigned_item(items, USAGE_PAGE, GENERIC_DESKTOP as u32);
+            unsigned_item(items, USAGE, 0x39);
+            signed_item(items, LOGICAL_MINIMUM, 0);
+            signed_item(items, LOGICAL_MAXIMUM, 7);
+            signed_item(items, PHYSICAL_MINIMUM, 0);
+            signed_item(items, PHYSICAL_MAXIMUM, 315);
+            // Degrees.
+            unsigned_item(items, UNIT, 0x14);
+            unsigned_item(items, REPORT_SIZE, 4);
+            unsigned_item(items, REPORT_COUNT, self.hats as u32);
+            unsigned_item(items, INPUT, VARIABLE | NULL_STATE);
+            padding(items, INPUT, 4 * self.hats as u32);
+            unsigned_item(items, UNIT, 0);
+            signed_item(items, PHYSICAL_MAXIMUM, 0);
+        }
+
+        let mut usage_page = None;
+        for (axis, info) in self.axes.iter() {
+            let (page, usage) = axis_usage(*axis).unwrap_or((VENDOR, 0));
+            if usage_page != Some(page) {
+                unsigned_item(items, USAGE_PAGE, page as u32);
+                usage_page = Some(page);
+            }
+            unsigned_item(items, USAGE, usage as u32);
+            signed_item(items, LOGICAL_MINIMUM, info.minimum);
+            signed_item(items, LOGICAL_MAXIMUM, info.maximum);
+            unsigned_item(items, REPORT_SIZE, axis_size(info));
+            unsigned_item(items, REPORT_COUNT, 1);
+            unsigned_item(items, INPUT, VARIABLE);
+        }
+
+        if self.battery {
+            // Part of the input report, so that the system learns about it
+            // from every report instead of asking for it.
+            unsigned_item(items, USAGE_PAGE, GENERIC_DEVICE as u32);
+            // The battery strength, in percent.
+            unsigned_item(items, USAGE, 0x20);
+            signed_item(items, LOGICAL_MINIMUM, 0);
+            signed_item(items, LOGICAL_MAXIMUM, 100);
+            unsigned_item(items, REPORT_SIZE, 8);
+            unsigned_item(items, REPORT_COUNT, 1);
+            unsigned_item(items, INPUT, VARIABLE);
+        }
+
+        if self.rumble || self.leds > 0 {
+            unsigned_item(items, REPORT_ID, OUTPUT_REPORT as u32);
+        }
+        if self.rumble {
+            // The strong and then the weak motor.
+            unsigned_item(items, USAGE_PAGE, VENDOR as u32);
+            unsigned_item(items, USAGE, 0x01);
+            unsigned_item(items, USAGE, 0x02);
+            signed_item(items, LOGICAL_MINIMUM, 0);
+            signed_item(items, LOGICAL_MAXIMUM, u16::MAX as i32);
+            unsigned_item(items, REPORT_SIZE, 16);
+            unsigned_item(items, REPORT_COUNT, 2);
+            unsigned_item(items, OUTPUT, VARIABLE);
+        }
+        if self.leds > 0 {
+            unsigned_item(items, USAGE_PAGE, LED as u32);
+            // Generic indicators.
+            unsigned_item(items, USAGE, 0x4b);
+            signed_item(items, LOGICAL_MINIMUM, 0);
+            signed_item(items, LOGICAL_MAXIMUM, 1);
+            unsigned_item(items, REPORT_SIZE, 1);
+            unsigned_item(items, REPORT_COUNT, self.leds as u32);
+            unsigned_item(items, OUTPUT, VARIABLE);
+            padding(items, OUTPUT, self.leds as u32);
+        }
+
+        items.push(END_COLLECTION);
+        bytes
+    }
+
+    /// The state of a device that was just plugged in, with every axis at
+    /// rest.
+    pub(crate) fn initial_state(&self) -> HidState {
+        HidState {
+            buttons: vec![false; self.buttons as usize],
+            hats: vec![(0, 0); self.hats as usize],
+            axes: self
+                .axes
+                .iter()
+                .map(|(_, info)| 0.clamp(info.minimum, info.maximum))
+                .collect(),
+            battery: 100,
+        }
+    }
+
+    pub(crate) fn input_report(&self, state: &HidState) -> Vec<u8> {
+        let mut bits = Bits::new(INPUT_REPORT);
+        for pressed in state.buttons.iter() {
+            bits.put(*pressed as u32, 1);
+        }
+        bits.pad();
+        for (x, y) in state.hats.iter() {
+            bits.put(hat_direction(*x, *y), 4);
+        }
+        bits.pad();
+        for ((_, info), value) in self.axes.iter().zip(state.axes.iter()) {
+            bits.put(*value as u32, axis_size(info));
+        }
+        if self.battery {
+            bits.put(state.battery.min(100) as u32, 8);
+        }
+        bits.bytes
+    }
+
+    /// What an output report stands for.
+    pub(crate) fn parse_output(&self, report: &[u8]) -> Vec<Output> {
+        let size = 1 + if self.rumble { 4 } else { 0 } + if self.leds > 0 { 1 } else { 0 };
+        if size == 1 || report.len() < size || report[0] != OUTPUT_REPORT {
+            return Vec::new();
+        }
+
+        let mut outputs = Vec::new();
+        let mut offset = 1;
+        if self.rumble {
+            outputs.push(Output::Rumble {
+                large_motor: u16::from_le_bytes([report[1], report[2]]),
+                small_motor: u16::from_le_bytes([report[3], report[4]]),
+            });
+            offset += 4;
+        }
+        if self.leds > 0 {
+            let mask = (1u16 << self.leds) - 1;
+            outputs.push(Output::PlayerLeds(report[offset] & mask as u8));
+        }
+        outputs
+    }
+}
+
+/// Pads fields of `bits` bits in total to a whole byte.
+fn padding(bytes: &mut Vec<u8>, kind: u8, bits: u32) {
+    let rest = bits % 8;
+    if rest != 0 {
+        unsigned_item(bytes, REPORT_SIZE, 8 - rest);
+        unsigned_item(bytes, REPORT_COUNT, 1);
+        unsigned_item(bytes, kind, CONSTANT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stick() -> AxisInfo {
+        AxisInfo {
+            minimum: -127,
+            maximum: 127,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        }
+    }
+
+    #[test]
+    fn describes_buttons_hats_and_axes() {
+        let descriptor = HidDescriptor::new()
+            .buttons(3)
+            .hats(1)
+            .axis(Axis::X, stick());
+        #[rustfmt::skip]
+        let expected = [
+            0x05, 0x01, 0x09, 0x04, 0xa1, 0x01, 0x85, 0x01,
+            0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03,
+            0x81, 0x02, 0x75, 0x05, 0x95, 0x01, 0x81, 0x01,
+            0x05, 0x01, 0x09, 0x39, 0x15, 0x00, 0x25, 0x07, 0x35, 0x00, 0x46, 0x3b, 0x01, 0x65,
+            0x14, 0x75, 0x04, 0x95, 0x01, 0x81, 0x42, 0x75, 0x04, 0x95, 0x01, 0x81, 0x01, 0x65,
+            0x00, 0x45, 0x00,
+            0x05, 0x01, 0x09, 0x30, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x10, 0x95, 0x01, 0x81, 0x02,
+            0xc0,
+        ];
+        assert_eq!(descriptor.to_bytes(), expected);
+    }
+
+    #[test]
+    fn packs_the_input_report() {
+        let descriptor = HidDescriptor::new()
+            .buttons(3)
+            .hats(1)
+            .axis(Axis::X, stick());
+        let mut state = descriptor.initial_state();
+        state.buttons[2] = true;
+        state.hats[0] = (1, 0);
+        state.axes[0] = -2;
+        assert_eq!(
+            descriptor.input_report(&state),
+            [0x01, 0x04, 0x02, 0xfe, 0xff]
+        );
+    }
+
+    #[test]
+    fn reports_the_battery_with_the_inputs() {
+        let descriptor = HidDescriptor::new().buttons(1).battery();
+        assert!(descriptor.to_bytes().ends_with(&[
+            0x05, 0x06, 0x09, 0x20, 0x15, 0x00, 0x25, 0x64, 0x75, 0x08, 0x95, 0x01, 0x81, 0x02,
+            0xc0,
+        ]));
+
+        let mut state = descriptor.initial_state();
+        assert_eq!(descriptor.input_report(&state), [0x01, 0x00, 100]);
+        state.battery = 42;
+        assert_eq!(descriptor.input_report(&state), [0x01, 0x00, 42]);
+    }
+
+    #[test]
+    fn unpacks_rumble_and_leds() {
+        let descriptor = HidDescriptor::new().rumble().leds(4);
+        let outputs = descriptor.parse_output(&[0x02, 0x34, 0x12, 0xff, 0x00, 0xf5]);
+        assert!(matches!(
+            outputs[..],
+            [
+                Output::Rumble {
+                    large_motor: 0x1234,
+                    small_motor: 0x00ff
+                },
+                Output::PlayerLeds(0x05)
+            ]
+        ));
+        assert!(descriptor.parse_output(&[0x02, 0x00]).is_empty());
+    }
+
+    #[test]
+    fn rejects_axes_without_usage() {
+        let descriptor = HidDescriptor::new().axis(Axis::Hat0X, stick());
+        assert!(descriptor.validate().is_err());
+    }
+}
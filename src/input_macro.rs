@@ -0,0 +1,557 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::api::{Axis, Button, Error, Input, ThumbStick};
+use crate::keyboard::Key;
+use crate::timer::Cancellation;
+
+/// Puts an input on the device a macro runs on.
+pub(crate) type InputSink = Arc<dyn Fn(Input) -> Result<(), Error> + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Step {
+    Input(Input),
+    /// Inputs put at the same instant.
+    Combo(Vec<Input>),
+    Wait(Duration),
+    /// Presses all of the buttons, waits for `duration` and then releases
+    /// them.
+    Hold {
+        buttons: Vec<Button>,
+        duration: Duration,
+    },
+    Macro(Macro),
+}
+
+/// An ordered sequence of inputs and the delays between them.
+///
+/// Besides building one step by step, a macro can be parsed from text with
+/// one step per line. `#` starts a comment. Numbered joystick buttons are
+/// written `Button0`, `Button1` and so on, and keys by their code, like
+/// `Key30`.
+///
+/// Durations are a non-negative decimal number followed right away by `us`,
+/// `ms` or `s`, like `50ms` or `1.5s`. Exponents are not accepted.
+///
+/// Buttons that are still pressed when a macro reaches its end stay pressed,
+/// so a macro can end with `press South` to keep holding it.
+///
+/// ```text
+/// press South East    # both at once
+/// wait 50ms
+/// release South East
+/// hold North 1.5s
+/// move left 0.5 -1.0
+/// axis Rx 200
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Macro {
+    pub steps: Vec<Step>,
+}
+
+impl Macro {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input(mut self, input: Input) -> Self {
+        self.steps.push(Step::Input(input));
+        self
+    }
+
+    pub fn combo(mut self, inputs: Vec<Input>) -> Self {
+        self.steps.push(Step::Combo(inputs));
+        self
+    }
+
+    pub fn wait(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Wait(duration));
+        self
+    }
+
+    pub fn hold(mut self, buttons: Vec<Button>, duration: Duration) -> Self {
+        self.steps.push(Step::Hold { buttons, duration });
+        self
+    }
+
+    /// Runs `other` after this macro.
+    pub fn then(mut self, other: Macro) -> Self {
+        self.steps.push(Step::Macro(other));
+        self
+    }
+
+    pub fn repeat(self, times: usize) -> Self {
+        Self {
+            steps: vec![Step::Macro(self); times],
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(Step::duration).sum()
+    }
+
+    /// Every input of the macro with its offset from the start.
+    pub(crate) fn timeline(&self) -> Vec<(Duration, Input)> {
+        let mut timeline = Vec::new();
+        self.push_timeline(Duration::from_secs(0), &mut timeline);
+        timeline
+    }
+
+    fn push_timeline(&self, mut offset: Duration, timeline: &mut Vec<(Duration, Input)>) {
+        for step in self.steps.iter() {
+            match step {
+                Step::Input(input) => timeline.push((offset, input.clone())),
+                Step::Combo(inputs) => {
+                    timeline.extend(inputs.iter().map(|input| (offset, input.clone())))
+                }
+                Step::Wait(_) => {}
+                Step::Hold { buttons, duration } => {
+                    timeline.extend(buttons.iter().map(|b| (offset, Input::Press(*b))));
+                    timeline.extend(
+                        buttons
+                            .iter()
+                            .map(|b| (offset + *duration, Input::Release(*b))),
+                    );
+                }
+                Step::Macro(r#macro) => r#macro.push_timeline(offset, timeline),
+            }
+            offset += step.duration();
+        }
+    }
+}
+
+impl Step {
+    fn duration(&self) -> Duration {
+        match self {
+            Step::Input(_) | Step::Combo(_) => Duration::from_secs(0),
+            Step::Wait(duration) | Step::Hold { duration, .. } => *duration,
+            Step::Macro(r#macro) => r#macro.duration(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MacroParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for MacroParseError {}
+
+impl FromStr for Macro {
+    type Err = MacroParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut r#macro = Macro::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() {
+                continue;
+            }
+
+            let step = parse_step(&tokens).map_err(|message| MacroParseError {
+                line: index + 1,
+                message,
+            })?;
+            r#macro.steps.push(step);
+        }
+
+        Ok(r#macro)
+    }
+}
+
+fn parse_step(tokens: &[&str]) -> Result<Step, String> {
+    let (command, arguments) = (tokens[0], &tokens[1..]);
+
+    match command {
+        "press" | "release" => {
+            if arguments.is_empty() {
+                return Err(format!("`{}` needs at least one button", command));
+            }
+            let mut inputs = arguments
+                .iter()
+                .map(|name| {
+                    let button = parse_button(name)?;
+                    Ok(if command == "press" {
+                        Input::Press(button)
+                    } else {
+                        Input::Release(button)
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            if inputs.len() == 1 {
+                Ok(Step::Input(inputs.remove(0)))
+            } else {
+                Ok(Step::Combo(inputs))
+            }
+        }
+        "hold" => match arguments.split_last() {
+            Some((duration, buttons)) if !buttons.is_empty() => Ok(Step::Hold {
+                buttons: buttons
+                    .iter()
+                    .map(|name| parse_button(name))
+                    .collect::<Result<_, _>>()?,
+                duration: parse_duration(duration)?,
+            }),
+            _ => Err("`hold` needs at least one button and a duration".to_string()),
+        },
+        "wait" => match arguments {
+            [duration] => Ok(Step::Wait(parse_duration(duration)?)),
+            _ => Err("`wait` needs a duration".to_string()),
+        },
+        "move" => match arguments {
+            [thumb_stick, x, y] => Ok(Step::Input(Input::Move {
+                thumb_stick: match thumb_stick.to_lowercase().as_str() {
+                    "left" => ThumbStick::Left,
+                    "right" => ThumbStick::Right,
+                    _ => return Err(format!("Unknown thumb stick `{}`", thumb_stick)),
+                },
+                x: parse_number(x)?,
+                y: parse_number(y)?,
+            })),
+            _ => Err("`move` needs a thumb stick, x and y".to_string()),
+        },
+        "axis" => match arguments {
+            [axis, value] => Ok(Step::Input(Input::Axis {
+                axis: parse_axis(axis)?,
+                value: parse_number(value)?,
+            })),
+            _ => Err("`axis` needs an axis and a value".to_string()),
+        },
+        _ => Err(format!("Unknown command `{}`", command)),
+    }
+}
+
+fn parse_button(name: &str) -> Result<Button, String> {
+    let button = match name.to_lowercase().as_str() {
+        "dpaddown" => Button::DpadDown,
+        "dpadup" => Button::DpadUp,
+        "dpadleft" => Button::DpadLeft,
+        "dpadright" => Button::DpadRight,
+        "north" => Button::North,
+        "south" => Button::South,
+        "west" => Button::West,
+        "east" => Button::East,
+        "start" => Button::Start,
+        "select" => Button::Select,
+        "triggerleft" => Button::TriggerLeft,
+        "triggerright" => Button::TriggerRight,
+        "triggerleft2" => Button::TriggerLeft2,
+        "triggerright2" => Button::TriggerRight2,
+        "thumbstickleft" => Button::ThumbStickLeft,
+        "thumbstickright" => Button::ThumbStickRight,
+        "mode" => Button::Mode,
+        "capture" => Button::Capture,
+        "paddle1" => Button::Paddle1,
+        "paddle2" => Button::Paddle2,
+        "paddle3" => Button::Paddle3,
+        "paddle4" => Button::Paddle4,
+        "mouseleft" => Button::MouseLeft,
+        "mouseright" => Button::MouseRight,
+        "mousemiddle" => Button::MouseMiddle,
+        "mouseside" => Button::MouseSide,
+        "mouseextra" => Button::MouseExtra,
+        other => match (
+            other.strip_prefix("button").map(str::parse),
+            other.strip_prefix("key").map(str::parse),
+        ) {
+            (Some(Ok(n)), _) => Button::Numbered(n),
+            (_, Some(Ok(code))) => Button::Key(Key(code)),
+            _ => return Err(format!("Unknown button `{}`", name)),
+        },
+    };
+    Ok(button)
+}
+
+fn parse_axis(name: &str) -> Result<Axis, String> {
+    let axis = match name.to_lowercase().as_str() {
+        "x" => Axis::X,
+        "y" => Axis::Y,
+        "z" => Axis::Z,
+        "rx" => Axis::Rx,
+        "ry" => Axis::Ry,
+        "rz" => Axis::Rz,
+        "throttle" => Axis::Throttle,
+        "rudder" => Axis::Rudder,
+        "wheel" => Axis::Wheel,
+        "gas" => Axis::Gas,
+        "brake" => Axis::Brake,
+        "hat0x" => Axis::Hat0X,
+        "hat0y" => Axis::Hat0Y,
+        "hat1x" => Axis::Hat1X,
+        "hat1y" => Axis::Hat1Y,
+        "hat2x" => Axis::Hat2X,
+        "hat2y" => Axis::Hat2Y,
+        "hat3x" => Axis::Hat3X,
+        "hat3y" => Axis::Hat3Y,
+        "pressure" => Axis::Pressure,
+        "distance" => Axis::Distance,
+        "tiltx" => Axis::TiltX,
+        "tilty" => Axis::TiltY,
+        "toolwidth" => Axis::ToolWidth,
+        "volume" => Axis::Volume,
+        "misc" => Axis::Misc,
+        _ => return Err(format!("Unknown axis `{}`", name)),
+    };
+    Ok(axis)
+}
+
+fn parse_number<T: FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("`{}` is not a valid number", text))
+}
+
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => text.split_at(index),
+        None => return Err(format!("`{}` has no unit (us, ms or s)", text)),
+    };
+    let number: f64 = parse_number(number)?;
+    if !(number >= 0.0 && number.is_finite()) {
+        return Err(format!("`{}` is not a valid duration", text));
+    }
+
+    match unit {
+        "us" => Ok(Duration::from_secs_f64(number / 1_000_000.0)),
+        "ms" => Ok(Duration::from_secs_f64(number / 1_000.0)),
+        "s" => Ok(Duration::from_secs_f64(number)),
+        _ => Err(format!("Unknown duration unit `{}`", unit)),
+    }
+}
+
+/// A macro running on a device. Dropping the handle lets the macro run to
+/// completion.
+pub struct MacroHandle {
+    cancellation: Arc<Cancellation>,
+    finished: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl MacroHandle {
+    /// Stops the macro. Buttons it pressed and did not release yet are
+    /// released, which is also the case when putting an input fails.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the macro to finish and returns the first error it ran into.
+    pub fn join(mut self) -> Result<(), Error> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(Error::Unknown("Macro thread panicked".to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn run(r#macro: &Macro, sink: InputSink) -> MacroHandle {
+    let cancellation = Arc::new(Cancellation::default());
+    let finished = Arc::new(AtomicBool::new(false));
+    let timeline = r#macro.timeline();
+
+    let thread = {
+        let cancellation = Arc::clone(&cancellation);
+        let finished = Arc::clone(&finished);
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            let mut held = HashSet::new();
+
+            let mut result = Ok(());
+            let mut interrupted = false;
+            for (offset, input) in timeline {
+                if !cancellation.wait_until(start + offset) {
+                    interrupted = true;
+                    break;
+                }
+                match &input {
+                    Input::Press(button) => {
+                        held.insert(*button);
+                    }
+                    Input::Release(button) => {
+                        held.remove(button);
+                    }
+                    _ => {}
+                }
+                result = sink(input);
+                if result.is_err() {
+                    interrupted = true;
+                    break;
+                }
+            }
+
+            // A macro that runs to its end leaves pressed what it pressed.
+            if !interrupted {
+                held.clear();
+            }
+            for button in held {
+                if let Err(e) = sink(Input::Release(button)) {
+                    log::error!("Failed to release {:?} after the macro: {:?}", button, e);
+                }
+            }
+
+            finished.store(true, Ordering::SeqCst);
+            result
+        })
+    };
+
+    MacroHandle {
+        cancellation,
+        finished,
+        thread: Some(thread),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeline_offsets_follow_waits_and_holds() {
+        let r#macro = Macro::new()
+            .input(Input::Press(Button::South))
+            .wait(Duration::from_millis(10))
+            .hold(vec![Button::East], Duration::from_millis(20))
+            .input(Input::Release(Button::South));
+
+        assert_eq!(
+            r#macro.timeline(),
+            vec![
+                (Duration::from_millis(0), Input::Press(Button::South)),
+                (Duration::from_millis(10), Input::Press(Button::East)),
+                (Duration::from_millis(30), Input::Release(Button::East)),
+                (Duration::from_millis(30), Input::Release(Button::South)),
+            ]
+        );
+        assert_eq!(r#macro.duration(), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn nested_macros_are_offset() {
+        let tap = Macro::new().hold(vec![Button::North], Duration::from_millis(5));
+        let r#macro = tap.repeat(2);
+
+        assert_eq!(r#macro.duration(), Duration::from_millis(10));
+        assert_eq!(r#macro.timeline()[2].0, Duration::from_millis(5));
+    }
+
+    #[test]
+    fn parses_text() {
+        let r#macro: Macro = "
+            # a comment
+            press South East
+            wait 1.5ms
+            hold north 2s
+            move left 0.5 -1
+            axis Rx 200
+            press Button12
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            r#macro.steps,
+            vec![
+                Step::Combo(vec![
+                    Input::Press(Button::South),
+                    Input::Press(Button::East)
+                ]),
+                Step::Wait(Duration::from_micros(1500)),
+                Step::Hold {
+                    buttons: vec![Button::North],
+                    duration: Duration::from_secs(2),
+                },
+                Step::Input(Input::Move {
+                    thumb_stick: ThumbStick::Left,
+                    x: 0.5,
+                    y: -1.0,
+                }),
+                Step::Input(Input::Axis {
+                    axis: Axis::Rx,
+                    value: 200,
+                }),
+                Step::Input(Input::Press(Button::Numbered(12))),
+            ]
+        );
+    }
+
+    fn recording_sink() -> (Arc<std::sync::Mutex<Vec<Input>>>, InputSink) {
+        let inputs = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink: InputSink = {
+            let inputs = Arc::clone(&inputs);
+            Arc::new(move |input| {
+                inputs.lock().unwrap().push(input);
+                Ok(())
+            })
+        };
+        (inputs, sink)
+    }
+
+    #[test]
+    fn cancelling_releases_held_buttons() {
+        let (inputs, sink) = recording_sink();
+        let r#macro = Macro::new().hold(vec![Button::South], Duration::from_secs(60));
+        let handle = run(&r#macro, sink);
+        while inputs.lock().unwrap().is_empty() {
+            std::thread::yield_now();
+        }
+        assert!(!handle.is_finished());
+        handle.cancel();
+        while !handle.is_finished() {
+            std::thread::yield_now();
+        }
+        handle.join().unwrap();
+
+        assert_eq!(
+            *inputs.lock().unwrap(),
+            vec![Input::Press(Button::South), Input::Release(Button::South)]
+        );
+    }
+
+    #[test]
+    fn finishing_keeps_buttons_pressed() {
+        let (inputs, sink) = recording_sink();
+        let r#macro: Macro = "press South\nwait 1ms".parse().unwrap();
+        run(&r#macro, sink).join().unwrap();
+
+        assert_eq!(*inputs.lock().unwrap(), vec![Input::Press(Button::South)]);
+    }
+
+    #[test]
+    fn reports_the_failing_line() {
+        let error = "wait 1ms\npress Nowhere".parse::<Macro>().unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn durations_need_a_plain_number_and_a_unit() {
+        assert_eq!(parse_duration("250us"), Ok(Duration::from_micros(250)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert!(parse_duration("50").is_err());
+        assert!(parse_duration("1e3ms").is_err());
+        assert!(parse_duration("-1ms").is_err());
+    }
+}
@@ -0,0 +1,445 @@
+use crate::api::InvalidInput;
+
+/// A key of a keyboard, by its `KEY_*` code from the Linux input event
+/// codes. Every code of the kernel's key ranges is a key, the common ones
+/// have a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Key(pub u16);
+
+impl Key {
+    pub const ESC: Key = Key(1);
+    pub const NUM1: Key = Key(2);
+    pub const NUM2: Key = Key(3);
+    pub const NUM3: Key = Key(4);
+    pub const NUM4: Key = Key(5);
+    pub const NUM5: Key = Key(6);
+    pub const NUM6: Key = Key(7);
+    pub const NUM7: Key = Key(8);
+    pub const NUM8: Key = Key(9);
+    pub const NUM9: Key = Key(10);
+    pub const NUM0: Key = Key(11);
+    pub const MINUS: Key = Key(12);
+    pub const EQUAL: Key = Key(13);
+    pub const BACKSPACE: Key = Key(14);
+    pub const TAB: Key = Key(15);
+    pub const Q: Key = Key(16);
+    pub const W: Key = Key(17);
+    pub const E: Key = Key(18);
+    pub const R: Key = Key(19);
+    pub const T: Key = Key(20);
+    pub const Y: Key = Key(21);
+    pub const U: Key = Key(22);
+    pub const I: Key = Key(23);
+    pub const O: Key = Key(24);
+    pub const P: Key = Key(25);
+    pub const LEFT_BRACE: Key = Key(26);
+    pub const RIGHT_BRACE: Key = Key(27);
+    pub const ENTER: Key = Key(28);
+    pub const LEFT_CTRL: Key = Key(29);
+    pub const A: Key = Key(30);
+    pub const S: Key = Key(31);
+    pub const D: Key = Key(32);
+    pub const F: Key = Key(33);
+    pub const G: Key = Key(34);
+    pub const H: Key = Key(35);
+    pub const J: Key = Key(36);
+    pub const K: Key = Key(37);
+    pub const L: Key = Key(38);
+    pub const SEMICOLON: Key = Key(39);
+    pub const APOSTROPHE: Key = Key(40);
+    pub const GRAVE: Key = Key(41);
+    pub const LEFT_SHIFT: Key = Key(42);
+    pub const BACKSLASH: Key = Key(43);
+    pub const Z: Key = Key(44);
+    pub const X: Key = Key(45);
+    pub const C: Key = Key(46);
+    pub const V: Key = Key(47);
+    pub const B: Key = Key(48);
+    pub const N: Key = Key(49);
+    pub const M: Key = Key(50);
+    pub const COMMA: Key = Key(51);
+    pub const DOT: Key = Key(52);
+    pub const SLASH: Key = Key(53);
+    pub const RIGHT_SHIFT: Key = Key(54);
+    pub const KP_ASTERISK: Key = Key(55);
+    pub const LEFT_ALT: Key = Key(56);
+    pub const SPACE: Key = Key(57);
+    pub const CAPS_LOCK: Key = Key(58);
+    pub const F1: Key = Key(59);
+    pub const F2: Key = Key(60);
+    pub const F3: Key = Key(61);
+    pub const F4: Key = Key(62);
+    pub const F5: Key = Key(63);
+    pub const F6: Key = Key(64);
+    pub const F7: Key = Key(65);
+    pub const F8: Key = Key(66);
+    pub const F9: Key = Key(67);
+    pub const F10: Key = Key(68);
+    pub const NUM_LOCK: Key = Key(69);
+    pub const SCROLL_LOCK: Key = Key(70);
+    pub const KP7: Key = Key(71);
+    pub const KP8: Key = Key(72);
+    pub const KP9: Key = Key(73);
+    pub const KP_MINUS: Key = Key(74);
+    pub const KP4: Key = Key(75);
+    pub const KP5: Key = Key(76);
+    pub const KP6: Key = Key(77);
+    pub const KP_PLUS: Key = Key(78);
+    pub const KP1: Key = Key(79);
+    pub const KP2: Key = Key(80);
+    pub const KP3: Key = Key(81);
+    pub const KP0: Key = Key(82);
+    pub const KP_DOT: Key = Key(83);
+    /// The key between left shift and Z on ISO keyboards.
+    pub const KEY_102ND: Key = Key(86);
+    pub const F11: Key = Key(87);
+    pub const F12: Key = Key(88);
+    pub const KP_ENTER: Key = Key(96);
+    pub const RIGHT_CTRL: Key = Key(97);
+    pub const KP_SLASH: Key = Key(98);
+    pub const SYSRQ: Key = Key(99);
+    /// AltGr on layouts that have it.
+    pub const RIGHT_ALT: Key = Key(100);
+    pub const HOME: Key = Key(102);
+    pub const UP: Key = Key(103);
+    pub const PAGE_UP: Key = Key(104);
+    pub const LEFT: Key = Key(105);
+    pub const RIGHT: Key = Key(106);
+    pub const END: Key = Key(107);
+    pub const DOWN: Key = Key(108);
+    pub const PAGE_DOWN: Key = Key(109);
+    pub const INSERT: Key = Key(110);
+    pub const DELETE: Key = Key(111);
+    pub const MUTE: Key = Key(113);
+    pub const VOLUME_DOWN: Key = Key(114);
+    pub const VOLUME_UP: Key = Key(115);
+    pub const POWER: Key = Key(116);
+    pub const PAUSE: Key = Key(119);
+    pub const LEFT_META: Key = Key(125);
+    pub const RIGHT_META: Key = Key(126);
+    pub const COMPOSE: Key = Key(127);
+    pub const NEXT_SONG: Key = Key(163);
+    pub const PLAY_PAUSE: Key = Key(164);
+    pub const PREVIOUS_SONG: Key = Key(165);
+    pub const STOP_CD: Key = Key(166);
+    pub const F13: Key = Key(183);
+    pub const F14: Key = Key(184);
+    pub const F15: Key = Key(185);
+    pub const F16: Key = Key(186);
+    pub const F17: Key = Key(187);
+    pub const F18: Key = Key(188);
+    pub const F19: Key = Key(189);
+    pub const F20: Key = Key(190);
+    pub const F21: Key = Key(191);
+    pub const F22: Key = Key(192);
+    pub const F23: Key = Key(193);
+    pub const F24: Key = Key(194);
+
+    /// The ranges of codes that are keys rather than buttons of joysticks,
+    /// mice and the like, up to `KEY_MAX`.
+    const RANGES: [(u16, u16); 3] = [(1, 0xff), (0x160, 0x21f), (0x230, 0x2bf)];
+
+    /// Every key a keyboard device has.
+    pub fn all() -> impl Iterator<Item = Key> {
+        Self::RANGES
+            .iter()
+            .flat_map(|(first, last)| (*first..=*last).map(Key))
+    }
+
+    /// Whether the code is in one of the kernel's key ranges.
+    pub fn is_key(self) -> bool {
+        Self::RANGES
+            .iter()
+            .any(|(first, last)| (*first..=*last).contains(&self.0))
+    }
+}
+
+/// The keys in the order of the lanes of a `LayoutTable`: the number row,
+/// then the rows starting with Q, A and the key left of Z.
+const LAYOUT_KEYS: [Key; 48] = [
+    Key::GRAVE,
+    Key::NUM1,
+    Key::NUM2,
+    Key::NUM3,
+    Key::NUM4,
+    Key::NUM5,
+    Key::NUM6,
+    Key::NUM7,
+    Key::NUM8,
+    Key::NUM9,
+    Key::NUM0,
+    Key::MINUS,
+    Key::EQUAL,
+    Key::Q,
+    Key::W,
+    Key::E,
+    Key::R,
+    Key::T,
+    Key::Y,
+    Key::U,
+    Key::I,
+    Key::O,
+    Key::P,
+    Key::LEFT_BRACE,
+    Key::RIGHT_BRACE,
+    Key::BACKSLASH,
+    Key::A,
+    Key::S,
+    Key::D,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::SEMICOLON,
+    Key::APOSTROPHE,
+    Key::KEY_102ND,
+    Key::Z,
+    Key::X,
+    Key::C,
+    Key::V,
+    Key::B,
+    Key::N,
+    Key::M,
+    Key::COMMA,
+    Key::DOT,
+    Key::SLASH,
+];
+
+/// What each key of `LAYOUT_KEYS` types on its own, with shift and with
+/// AltGr. A space stands for nothing, since the space bar types spaces.
+struct LayoutTable {
+    plain: &'static str,
+    shift: &'static str,
+    alt_gr: &'static str,
+    /// Characters whose key is a dead key, which type themselves when they
+    /// are followed by a space.
+    dead: &'static str,
+}
+
+impl LayoutTable {
+    /// Whether the key in lane `i` types a letter, which shift turns into its
+    /// capital.
+    fn is_letter(&self, i: usize) -> bool {
+        let plain = self.plain.chars().nth(i);
+        let shift = self.shift.chars().nth(i);
+        match (plain, shift) {
+            (Some(plain), Some(shift)) => {
+                plain.is_lowercase() && plain.to_uppercase().eq(std::iter::once(shift))
+            }
+            _ => false,
+        }
+    }
+}
+
+const US: LayoutTable = LayoutTable {
+    plain: "`1234567890-=qwertyuiop[]\\asdfghjkl;' zxcvbnm,./",
+    shift: "~!@#$%^&*()_+QWERTYUIOP{}|ASDFGHJKL:\" ZXCVBNM<>?",
+    alt_gr: "                                                ",
+    dead: "",
+};
+
+const UK: LayoutTable = LayoutTable {
+    plain: "`1234567890-=qwertyuiop[]#asdfghjkl;'\\zxcvbnm,./",
+    shift: "¬!\"£$%^&*()_+QWERTYUIOP{}~ASDFGHJKL:@|ZXCVBNM<>?",
+    alt_gr: "¦   €                                           ",
+    dead: "",
+};
+
+const GERMAN: LayoutTable = LayoutTable {
+    plain: "^1234567890ß´qwertzuiopü+#asdfghjklöä<yxcvbnm,.-",
+    shift: "°!\"§$%&/()=?`QWERTZUIOPÜ*'ASDFGHJKLÖÄ>YXCVBNM;:_",
+    alt_gr: "  ²³   {[]}\\ @ €        ~            |      µ   ",
+    dead: "^´`",
+};
+
+// The circumflex of the key right of P is dead, so it is typed with AltGr
+// and 9 instead.
+const FRENCH: LayoutTable = LayoutTable {
+    plain: "²&é\"'(-è_çà)=azertyuiop $*qsdfghjklmù<wxcvbn,;:!",
+    shift: " 1234567890°+AZERTYUIOP¨£µQSDFGHJKLM%>WXCVBN?./§",
+    alt_gr: "  ~#{[|`\\^@]}  €                                ",
+    dead: "~`¨",
+};
+
+/// The keyboard layout that `type_text` types with. It has to match the
+/// layout the system interprets the keys with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Layout {
+    /// US QWERTY.
+    Us,
+    /// UK QWERTY.
+    Uk,
+    /// German QWERTZ.
+    German,
+    /// French AZERTY.
+    French,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Us
+    }
+}
+
+impl Layout {
+    fn table(self) -> &'static LayoutTable {
+        match self {
+            Layout::Us => &US,
+            Layout::Uk => &UK,
+            Layout::German => &GERMAN,
+            Layout::French => &FRENCH,
+        }
+    }
+
+    /// The key that types `c` and the modifier to hold down for it, if any.
+    /// The last value tells whether the key is dead.
+    ///
+    /// Caps lock swaps the plain and shifted lane of the letter keys, so shift
+    /// is held for lower case letters while it is on.
+    fn find(self, c: char, caps_lock: bool) -> Option<(Key, Option<Key>, bool)> {
+        match c {
+            ' ' => return Some((Key::SPACE, None, false)),
+            '\n' => return Some((Key::ENTER, None, false)),
+            '\t' => return Some((Key::TAB, None, false)),
+            _ => {}
+        }
+
+        let table = self.table();
+        let lanes = [
+            (table.plain, None),
+            (table.shift, Some(Key::LEFT_SHIFT)),
+            (table.alt_gr, Some(Key::RIGHT_ALT)),
+        ];
+        lanes.iter().find_map(|(lane, modifier)| {
+            let i = lane.chars().position(|other| other == c)?;
+            let modifier = match modifier {
+                None if caps_lock && table.is_letter(i) => Some(Key::LEFT_SHIFT),
+                Some(Key::LEFT_SHIFT) if caps_lock && table.is_letter(i) => None,
+                modifier => *modifier,
+            };
+            Some((LAYOUT_KEYS[i], modifier, table.dead.contains(c)))
+        })
+    }
+
+    /// The presses and releases that type `text` while caps lock is on or
+    /// off, in order. Fails with the first character the layout cannot type,
+    /// before anything is typed.
+    pub(crate) fn keystrokes(
+        self,
+        text: &str,
+        caps_lock: bool,
+    ) -> Result<Vec<(Key, bool)>, InvalidInput> {
+        let mut keystrokes = Vec::new();
+        for c in text.chars() {
+            let (key, modifier, dead) = self
+                .find(c, caps_lock)
+                .ok_or(InvalidInput::UnsupportedCharacter(c))?;
+
+            if let Some(modifier) = modifier {
+                keystrokes.push((modifier, true));
+            }
+            keystrokes.push((key, true));
+            keystrokes.push((key, false));
+            if let Some(modifier) = modifier {
+                keystrokes.push((modifier, false));
+            }
+            if dead {
+                keystrokes.push((Key::SPACE, true));
+                keystrokes.push((Key::SPACE, false));
+            }
+        }
+        Ok(keystrokes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_lanes_cover_every_key() {
+        for layout in [Layout::Us, Layout::Uk, Layout::German, Layout::French].iter() {
+            let table = layout.table();
+            for lane in [table.plain, table.shift, table.alt_gr].iter() {
+                assert_eq!(lane.chars().count(), LAYOUT_KEYS.len(), "{:?}", layout);
+            }
+        }
+    }
+
+    #[test]
+    fn types_with_shift_and_alt_gr() {
+        assert_eq!(
+            Layout::Us.keystrokes("A!", false).unwrap(),
+            vec![
+                (Key::LEFT_SHIFT, true),
+                (Key::A, true),
+                (Key::A, false),
+                (Key::LEFT_SHIFT, false),
+                (Key::LEFT_SHIFT, true),
+                (Key::NUM1, true),
+                (Key::NUM1, false),
+                (Key::LEFT_SHIFT, false),
+            ]
+        );
+        assert_eq!(
+            Layout::German.keystrokes("@", false).unwrap(),
+            vec![
+                (Key::RIGHT_ALT, true),
+                (Key::Q, true),
+                (Key::Q, false),
+                (Key::RIGHT_ALT, false),
+            ]
+        );
+        assert_eq!(
+            Layout::French.keystrokes("a", false).unwrap()[0],
+            (Key::Q, true)
+        );
+    }
+
+    #[test]
+    fn caps_lock_inverts_shift_on_letters() {
+        assert_eq!(
+            Layout::Us.keystrokes("aB1", true).unwrap(),
+            vec![
+                (Key::LEFT_SHIFT, true),
+                (Key::A, true),
+                (Key::A, false),
+                (Key::LEFT_SHIFT, false),
+                (Key::B, true),
+                (Key::B, false),
+                (Key::NUM1, true),
+                (Key::NUM1, false),
+            ]
+        );
+        assert_eq!(Layout::German.keystrokes("Ä", true).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dead_keys_are_followed_by_a_space() {
+        let keystrokes = Layout::German.keystrokes("^", false).unwrap();
+        assert_eq!(keystrokes[0], (Key::GRAVE, true));
+        assert_eq!(keystrokes[2], (Key::SPACE, true));
+        assert_eq!(Layout::French.keystrokes("^", false).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn rejects_characters_the_layout_lacks() {
+        assert_eq!(
+            Layout::Us.keystrokes("ok€", false),
+            Err(InvalidInput::UnsupportedCharacter('€'))
+        );
+        assert!(Layout::Uk.keystrokes("£5 €", false).is_ok());
+    }
+
+    #[test]
+    fn knows_key_ranges() {
+        assert!(Key::A.is_key());
+        assert!(!Key(0x130).is_key());
+        assert_eq!(Key::all().filter(|key| *key == Key::F24).count(), 1);
+    }
+}
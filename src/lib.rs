@@ -1,6 +1,44 @@
 mod api;
 pub use api::*;
 
+mod config;
+pub use config::{
+    DeviceConfig, DeviceKind, FlightConfig, JoystickConfig, KeyboardConfig, MotionConfig,
+    PointerConfig, TouchpadConfig, WheelConfig,
+};
+
+mod force_feedback;
+pub use force_feedback::{
+    Condition, Effect, Envelope, HdRumble, PlayedEffect, TriggerEffect, TriggerEffectKind,
+};
+
+mod keyboard;
+pub use keyboard::{Key, Layout};
+
+mod hid_descriptor;
+pub use hid_descriptor::HidDescriptor;
+
+mod stick;
+pub use stick::*;
+
+mod validation;
+pub use validation::*;
+
+mod turbo;
+pub use turbo::Turbo;
+
+mod timer;
+
+mod input_macro;
+pub use input_macro::{Macro, MacroHandle, MacroParseError, Step};
+
+mod scheduler;
+pub use scheduler::{At, Drift, Scheduler};
+
+mod watchdog;
+
+mod scroll;
+
 #[cfg(target_os = "linux")]
 mod linux_impl;
 #[cfg(target_os = "linux")]
@@ -36,4 +74,10 @@ mod type_tests {
         is_sync::<Bus>();
         is_sync::<Device>();
     }
+
+    #[test]
+    fn device_halves_are_send() {
+        is_send::<InputHandle>();
+        is_send::<OutputHandle>();
+    }
 }
@@ -6,15 +6,27 @@ include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
 pub mod consts {
     pub use super::{
-        ABS_RX, ABS_RY, ABS_X, ABS_Y, BTN_A, BTN_B, BTN_DPAD_DOWN, BTN_DPAD_LEFT, BTN_DPAD_RIGHT,
-        BTN_DPAD_UP, BTN_SELECT, BTN_START, BTN_THUMBL, BTN_THUMBR, BTN_TL, BTN_TL2, BTN_TR,
-        BTN_TR2, BTN_X, BTN_Y, EV_ABS, EV_FF, EV_KEY, EV_SYN, EV_UINPUT, FF_MAX_EFFECTS, FF_RUMBLE,
-        SYN_REPORT, UI_FF_ERASE, UI_FF_UPLOAD,
+        ABS_BRAKE, ABS_DISTANCE, ABS_GAS, ABS_HAT0X, ABS_HAT0Y, ABS_HAT1X, ABS_HAT1Y, ABS_HAT2X,
+        ABS_HAT2Y, ABS_HAT3X, ABS_HAT3Y, ABS_MISC, ABS_MT_POSITION_X, ABS_MT_POSITION_Y,
+        ABS_MT_SLOT, ABS_MT_TRACKING_ID, ABS_PRESSURE, ABS_RUDDER, ABS_RX, ABS_RY, ABS_RZ,
+        ABS_THROTTLE, ABS_TILT_X, ABS_TILT_Y, ABS_TOOL_WIDTH, ABS_VOLUME, ABS_WHEEL, ABS_X, ABS_Y,
+        ABS_Z, BTN_A, BTN_B, BTN_DPAD_DOWN, BTN_DPAD_LEFT, BTN_DPAD_RIGHT, BTN_DPAD_UP, BTN_EXTRA,
+        BTN_JOYSTICK, BTN_LEFT, BTN_MIDDLE, BTN_MODE, BTN_RIGHT, BTN_SELECT, BTN_SIDE, BTN_START,
+        BTN_THUMBL, BTN_THUMBR, BTN_TL, BTN_TL2, BTN_TOOL_DOUBLETAP, BTN_TOOL_FINGER, BTN_TOOL_PEN,
+        BTN_TOOL_QUADTAP, BTN_TOOL_QUINTTAP, BTN_TOOL_TRIPLETAP, BTN_TOUCH, BTN_TR, BTN_TR2,
+        BTN_TRIGGER_HAPPY1, BTN_TRIGGER_HAPPY5, BTN_TRIGGER_HAPPY6, BTN_TRIGGER_HAPPY7,
+        BTN_TRIGGER_HAPPY8, BTN_X, BTN_Y, EV_ABS, EV_FF, EV_KEY, EV_LED, EV_MSC, EV_REL, EV_SYN,
+        EV_UINPUT, FF_AUTOCENTER, FF_CONSTANT, FF_DAMPER, FF_GAIN, FF_MAX_EFFECTS, FF_RUMBLE,
+        FF_SPRING, INPUT_PROP_ACCELEROMETER, INPUT_PROP_BUTTONPAD, INPUT_PROP_DIRECT,
+        INPUT_PROP_POINTER, KEY_RECORD, LED_CAPSL, LED_NUML, LED_SCROLLL, MSC_TIMESTAMP,
+        REL_HWHEEL, REL_HWHEEL_HI_RES, REL_WHEEL, REL_WHEEL_HI_RES, REL_X, REL_Y, SYN_REPORT,
+        UI_FF_ERASE, UI_FF_UPLOAD,
     };
 }
 
 pub mod types {
     pub use super::{
-        input_event, uinput_abs_setup, uinput_ff_erase, uinput_ff_upload, uinput_setup,
+        ff_condition_effect, ff_envelope, input_event, uinput_abs_setup, uinput_ff_erase,
+        uinput_ff_upload, uinput_setup,
     };
 }
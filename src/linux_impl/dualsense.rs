@@ -0,0 +1,353 @@
+use std::time::Instant;
+
+use super::{
+    bindings::consts::*,
+    playstation::{self, Buttons, Touchpad},
+    uhid::HidProfile,
+};
+use crate::api::*;
+use crate::force_feedback::TriggerEffect;
+
+const VENDOR_ID: u32 = 0x054c;
+const PRODUCT_ID: u32 = 0x0ce6;
+
+const INPUT_REPORT: u8 = 0x01;
+const OUTPUT_REPORT: u8 = 0x02;
+const INPUT_REPORT_SIZE: usize = 64;
+const OUTPUT_REPORT_SIZE: usize = 48;
+
+const FEATURE_CALIBRATION: u8 = 0x05;
+const FEATURE_PAIRING_INFO: u8 = 0x09;
+const FEATURE_FIRMWARE_INFO: u8 = 0x20;
+
+// Which parts of an output report are valid, by flag byte.
+const FLAG0_COMPATIBLE_VIBRATION: u8 = 0x01;
+const FLAG0_RIGHT_TRIGGER: u8 = 0x04;
+const FLAG0_LEFT_TRIGGER: u8 = 0x08;
+const FLAG1_MIC_LED: u8 = 0x01;
+const FLAG1_LIGHTBAR: u8 = 0x04;
+const FLAG1_PLAYER_LEDS: u8 = 0x10;
+const FLAG2_COMPATIBLE_VIBRATION2: u8 = 0x04;
+
+/// Where each trigger's effect starts in the output report, as its mode
+/// followed by its parameters.
+const RIGHT_TRIGGER_EFFECT: usize = 11;
+const LEFT_TRIGGER_EFFECT: usize = 22;
+
+/// The report descriptor of a DualSense (CFI-ZCT1) on USB.
+#[rustfmt::skip]
+const DESCRIPTOR: [u8; 273] = [
+    0x05, 0x01, 0x09, 0x05, 0xa1, 0x01, 0x85, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x32, 0x09, 0x35,
+    0x09, 0x33, 0x09, 0x34, 0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x06, 0x81, 0x02, 0x06,
+    0x00, 0xff, 0x09, 0x20, 0x95, 0x01, 0x81, 0x02, 0x05, 0x01, 0x09, 0x39, 0x15, 0x00, 0x25, 0x07,
+    0x35, 0x00, 0x46, 0x3b, 0x01, 0x65, 0x14, 0x75, 0x04, 0x95, 0x01, 0x81, 0x42, 0x65, 0x00, 0x05,
+    0x09, 0x19, 0x01, 0x29, 0x0f, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x0f, 0x81, 0x02, 0x06,
+    0x00, 0xff, 0x09, 0x21, 0x95, 0x0d, 0x81, 0x02, 0x06, 0x00, 0xff, 0x09, 0x22, 0x15, 0x00, 0x26,
+    0xff, 0x00, 0x75, 0x08, 0x95, 0x34, 0x81, 0x02, 0x85, 0x02, 0x09, 0x23, 0x95, 0x2f, 0x91, 0x02,
+    0x85, 0x05, 0x09, 0x33, 0x95, 0x28, 0xb1, 0x02, 0x85, 0x08, 0x09, 0x34, 0x95, 0x2f, 0xb1, 0x02,
+    0x85, 0x09, 0x09, 0x24, 0x95, 0x13, 0xb1, 0x02, 0x85, 0x0a, 0x09, 0x25, 0x95, 0x1a, 0xb1, 0x02,
+    0x85, 0x20, 0x09, 0x26, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0x21, 0x09, 0x27, 0x95, 0x04, 0xb1, 0x02,
+    0x85, 0x22, 0x09, 0x40, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0x80, 0x09, 0x28, 0x95, 0x3f, 0xb1, 0x02,
+    0x85, 0x81, 0x09, 0x29, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0x82, 0x09, 0x2a, 0x95, 0x09, 0xb1, 0x02,
+    0x85, 0x83, 0x09, 0x2b, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0x84, 0x09, 0x2c, 0x95, 0x3f, 0xb1, 0x02,
+    0x85, 0x85, 0x09, 0x2d, 0x95, 0x02, 0xb1, 0x02, 0x85, 0xa0, 0x09, 0x2e, 0x95, 0x01, 0xb1, 0x02,
+    0x85, 0xe0, 0x09, 0x2f, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0xf0, 0x09, 0x30, 0x95, 0x3f, 0xb1, 0x02,
+    0x85, 0xf1, 0x09, 0x31, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0xf2, 0x09, 0x32, 0x95, 0x0f, 0xb1, 0x02,
+    0x85, 0xf4, 0x09, 0x35, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0xf5, 0x09, 0x36, 0x95, 0x03, 0xb1, 0x02,
+    0xc0,
+];
+
+/// A DualSense on USB, as `hid-playstation` drives it.
+pub(super) struct DualSense {
+    mac: [u8; 6],
+    /// The input report, except for the parts that are filled in when it is
+    /// sent.
+    report: [u8; INPUT_REPORT_SIZE],
+    buttons: Buttons,
+    triggers: [u8; 2],
+    touchpad: Touchpad,
+    sequence: u8,
+    start: Instant,
+}
+
+impl DualSense {
+    pub fn new(mac: [u8; 6]) -> Self {
+        let mut report = [0; INPUT_REPORT_SIZE];
+        report[0] = INPUT_REPORT;
+        report[1..5].copy_from_slice(&[0x80; 4]);
+        // Fully charged.
+        report[53] = 0x20 | 10;
+
+        Self {
+            mac,
+            report,
+            buttons: Buttons::default(),
+            triggers: [0; 2],
+            touchpad: Touchpad::new(1920, 1080),
+            sequence: 0,
+            start: Instant::now(),
+        }
+    }
+}
+
+fn trigger_effect(report: &[u8], offset: usize) -> TriggerEffect {
+    let mut parameters = [0; 10];
+    parameters.copy_from_slice(&report[offset + 1..offset + 11]);
+    TriggerEffect {
+        mode: report[offset],
+        parameters,
+    }
+}
+
+impl HidProfile for DualSense {
+    fn name(&self) -> &str {
+        "Sony Interactive Entertainment DualSense Wireless Controller"
+    }
+
+    fn ids(&self) -> (u32, u32, u32) {
+        (VENDOR_ID, PRODUCT_ID, 0x0100)
+    }
+
+    fn descriptor(&self) -> &[u8] {
+        &DESCRIPTOR
+    }
+
+    fn apply(&mut self, r#type: u16, code: u16, value: i32) {
+        match (r#type as u32, code as u32) {
+            (EV_KEY, code) => self.buttons.apply(code, value != 0),
+            (EV_ABS, ABS_X) => self.report[1] = playstation::stick(value),
+            (EV_ABS, ABS_Y) => self.report[2] = playstation::stick(value),
+            (EV_ABS, ABS_RX) => self.report[3] = playstation::stick(value),
+            (EV_ABS, ABS_RY) => self.report[4] = playstation::stick(value),
+            (EV_ABS, ABS_Z) => self.triggers[0] = value as u8,
+            (EV_ABS, ABS_RZ) => self.triggers[1] = value as u8,
+            _ => {}
+        }
+    }
+
+    fn motion(&mut self, accel: [f32; 3], gyro: [f32; 3]) -> Result<(), Error> {
+        self.report[16..28].copy_from_slice(&playstation::motion(accel, gyro)?);
+        Ok(())
+    }
+
+    fn touch(&mut self, finger: u8, x: f32, y: f32) -> Result<(), Error> {
+        self.touchpad.touch(finger, x, y)
+    }
+
+    fn touch_up(&mut self, finger: u8) -> Result<(), Error> {
+        self.touchpad.touch_up(finger)
+    }
+
+    fn touchpad_click(&mut self, pressed: bool) -> Result<(), Error> {
+        self.buttons.set_touchpad_click(pressed);
+        Ok(())
+    }
+
+    fn release_touchpad(&mut self) {
+        self.touchpad.release();
+        self.buttons.set_touchpad_click(false);
+    }
+
+    fn set_battery(&mut self, level: u8, charging: bool) -> Result<(), Error> {
+        // The high nibble is discharging, charging or full.
+        let status = match (charging, level) {
+            (true, 100) => 0x20,
+            (true, _) => 0x10,
+            (false, _) => 0x00,
+        };
+        self.report[53] = status | playstation::battery_capacity(level);
+        Ok(())
+    }
+
+    fn input_report(&mut self) -> Vec<u8> {
+        let mut report = self.report;
+
+        report[5] = self.buttons.trigger(0, self.triggers[0]);
+        report[6] = self.buttons.trigger(1, self.triggers[1]);
+        report[7] = self.sequence;
+        report[8..11].copy_from_slice(&self.buttons.bytes());
+
+        // The sensors count time in units of a third of a microsecond.
+        let timestamp = (self.start.elapsed().as_micros() * 3) as u32;
+        report[28..32].copy_from_slice(&timestamp.to_le_bytes());
+
+        self.touchpad.write_points(&mut report[33..41]);
+
+        self.sequence = self.sequence.wrapping_add(1);
+        report.to_vec()
+    }
+
+    fn output(&mut self, report: &[u8]) -> Vec<Output> {
+        if report.len() < OUTPUT_REPORT_SIZE || report[0] != OUTPUT_REPORT {
+            return Vec::new();
+        }
+
+        let (flag0, flag1, flag2) = (report[1], report[2], report[39]);
+        let mut outputs = Vec::new();
+        if flag0 & FLAG0_COMPATIBLE_VIBRATION != 0 || flag2 & FLAG2_COMPATIBLE_VIBRATION2 != 0 {
+            // The left motor is the strong one.
+            outputs.push(Output::Rumble {
+                large_motor: u16::from_be_bytes([report[4], report[4]]),
+                small_motor: u16::from_be_bytes([report[3], report[3]]),
+            });
+        }
+        if flag0 & FLAG0_LEFT_TRIGGER != 0 {
+            outputs.push(Output::TriggerEffect {
+                trigger: Trigger::Left,
+                effect: trigger_effect(report, LEFT_TRIGGER_EFFECT),
+            });
+        }
+        if flag0 & FLAG0_RIGHT_TRIGGER != 0 {
+            outputs.push(Output::TriggerEffect {
+                trigger: Trigger::Right,
+                effect: trigger_effect(report, RIGHT_TRIGGER_EFFECT),
+            });
+        }
+        if flag1 & FLAG1_MIC_LED != 0 {
+            outputs.push(Output::MicLed { on: report[9] != 0 });
+        }
+        if flag1 & FLAG1_LIGHTBAR != 0 {
+            outputs.push(Output::Lightbar {
+                red: report[45],
+                green: report[46],
+                blue: report[47],
+            });
+        }
+        if flag1 & FLAG1_PLAYER_LEDS != 0 {
+            outputs.push(Output::PlayerLeds(report[44] & 0x1f));
+        }
+        outputs
+    }
+
+    fn feature_report(&self, number: u8) -> Option<Vec<u8>> {
+        match number {
+            FEATURE_CALIBRATION => Some(playstation::calibration_report(number, 41)),
+            FEATURE_PAIRING_INFO => Some(playstation::mac_report(number, 20, self.mac)),
+            FEATURE_FIRMWARE_INFO => {
+                let mut report = vec![0; 64];
+                report[0] = number;
+                // Hardware and firmware versions, and the version of the
+                // update, from which on drivers use the newer rumble flag.
+                report[24..28].copy_from_slice(&0x0000_0411u32.to_le_bytes());
+                report[28..32].copy_from_slice(&0x0110_0100u32.to_le_bytes());
+                report[44..46].copy_from_slice(&0x0224u16.to_le_bytes());
+                Some(report)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(ds: &mut DualSense, code: u32, pressed: bool) {
+        ds.apply(EV_KEY as u16, code as u16, pressed as i32);
+    }
+
+    #[test]
+    fn packs_buttons_sticks_and_triggers() {
+        let mut ds = DualSense::new([0; 6]);
+        let report = ds.input_report();
+        assert_eq!(report[1..5], [0x80; 4]);
+        assert_eq!(report[7..11], [0, 0x08, 0x00, 0x00]);
+
+        press(&mut ds, BTN_Y, true);
+        press(&mut ds, BTN_DPAD_LEFT, true);
+        press(&mut ds, BTN_DPAD_DOWN, true);
+        press(&mut ds, BTN_THUMBR, true);
+        press(&mut ds, BTN_TL2, true);
+        ds.apply(EV_ABS as u16, ABS_Y as u16, -128);
+        ds.apply(EV_ABS as u16, ABS_RZ as u16, 200);
+        ds.touchpad_click(true).unwrap();
+        let report = ds.input_report();
+        assert_eq!(report[1..5], [0x80, 0x00, 0x80, 0x80]);
+        assert_eq!(report[5..7], [0xff, 200]);
+        assert_eq!(report[7], 1);
+        assert_eq!(report[8..11], [0x80 | 5, 0x80 | 0x04, 0x02]);
+    }
+
+    #[test]
+    fn packs_touch_points() {
+        let mut ds = DualSense::new([0; 6]);
+        ds.touch(0, 0.5, 0.0).unwrap();
+        ds.touch(1, 0.0, 0.5).unwrap();
+        let report = ds.input_report();
+        // 960 by 0 and 0 by 540, with tracking ids in the order the fingers
+        // went down.
+        assert_eq!(report[33..41], [0, 0xc0, 0x03, 0x00, 1, 0x00, 0xc0, 0x21]);
+
+        ds.release_touchpad();
+        assert_eq!(ds.input_report()[33..41], [0x80, 0, 0, 0, 0x80, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_rumble_trigger_effects_and_leds() {
+        let mut ds = DualSense::new([0; 6]);
+        let mut report = [0; OUTPUT_REPORT_SIZE];
+        report[0] = OUTPUT_REPORT;
+        report[1] = FLAG0_COMPATIBLE_VIBRATION | FLAG0_LEFT_TRIGGER | FLAG0_RIGHT_TRIGGER;
+        report[2] = FLAG1_MIC_LED | FLAG1_LIGHTBAR | FLAG1_PLAYER_LEDS;
+        report[3] = 0x10;
+        report[4] = 0x20;
+        report[9] = 1;
+        report[RIGHT_TRIGGER_EFFECT] = 0x21;
+        report[RIGHT_TRIGGER_EFFECT + 1] = 0xaa;
+        report[LEFT_TRIGGER_EFFECT] = 0x26;
+        report[LEFT_TRIGGER_EFFECT + 10] = 0xbb;
+        report[44] = 0xe4;
+        report[45..48].copy_from_slice(&[4, 5, 6]);
+
+        let outputs = ds.output(&report);
+        assert_eq!(outputs.len(), 6);
+        assert!(matches!(
+            outputs[0],
+            Output::Rumble {
+                large_motor: 0x2020,
+                small_motor: 0x1010,
+            }
+        ));
+        match &outputs[1..3] {
+            [Output::TriggerEffect {
+                trigger: Trigger::Left,
+                effect: left,
+            }, Output::TriggerEffect {
+                trigger: Trigger::Right,
+                effect: right,
+            }] => {
+                assert_eq!(left.mode, 0x26);
+                assert_eq!(left.parameters[9], 0xbb);
+                assert_eq!(right.mode, 0x21);
+                assert_eq!(right.parameters[0], 0xaa);
+            }
+            outputs => panic!("Expected both trigger effects, got {:?}", outputs),
+        }
+        assert!(matches!(outputs[3], Output::MicLed { on: true }));
+        assert!(matches!(
+            outputs[4],
+            Output::Lightbar {
+                red: 4,
+                green: 5,
+                blue: 6,
+            }
+        ));
+        assert!(matches!(outputs[5], Output::PlayerLeds(0x04)));
+
+        assert!(ds.output(&report[..OUTPUT_REPORT_SIZE - 1]).is_empty());
+    }
+
+    #[test]
+    fn answers_feature_reports() {
+        let ds = DualSense::new([1, 2, 3, 4, 5, 6]);
+        let pairing = ds.feature_report(FEATURE_PAIRING_INFO).unwrap();
+        assert_eq!(pairing.len(), 20);
+        assert_eq!(pairing[..7], [FEATURE_PAIRING_INFO, 6, 5, 4, 3, 2, 1]);
+
+        let calibration = ds.feature_report(FEATURE_CALIBRATION).unwrap();
+        assert_eq!(calibration.len(), 41);
+
+        let firmware = ds.feature_report(FEATURE_FIRMWARE_INFO).unwrap();
+        assert_eq!(firmware[44..46], [0x24, 0x02]);
+    }
+}
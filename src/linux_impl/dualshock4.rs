@@ -0,0 +1,339 @@
+use std::time::{Duration, Instant};
+
+use super::{
+    bindings::consts::*,
+    playstation::{self, Buttons, Touchpad},
+    uhid::HidProfile,
+};
+use crate::api::*;
+
+const VENDOR_ID: u32 = 0x054c;
+const PRODUCT_ID: u32 = 0x09cc;
+
+const INPUT_REPORT: u8 = 0x01;
+const OUTPUT_REPORT: u8 = 0x05;
+const INPUT_REPORT_SIZE: usize = 64;
+
+const FEATURE_CALIBRATION: u8 = 0x02;
+const FEATURE_PAIRING_INFO: u8 = 0x12;
+/// The MAC address as older drivers ask for it.
+const FEATURE_MAC_ADDRESS: u8 = 0x81;
+const FEATURE_FIRMWARE_INFO: u8 = 0xa3;
+
+const OUTPUT_RUMBLE: u8 = 0x01;
+const OUTPUT_LIGHTBAR: u8 = 0x02;
+const OUTPUT_LIGHTBAR_BLINK: u8 = 0x04;
+
+/// The report descriptor of a DualShock 4 v2 (CUH-ZCT2) on USB.
+#[rustfmt::skip]
+const DESCRIPTOR: [u8; 459] = [
+    0x05, 0x01, 0x09, 0x05, 0xa1, 0x01, 0x85, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x32, 0x09, 0x35,
+    0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x04, 0x81, 0x02, 0x09, 0x39, 0x15, 0x00, 0x25,
+    0x07, 0x35, 0x00, 0x46, 0x3b, 0x01, 0x65, 0x14, 0x75, 0x04, 0x95, 0x01, 0x81, 0x42, 0x65, 0x00,
+    0x05, 0x09, 0x19, 0x01, 0x29, 0x0e, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x0e, 0x81, 0x02,
+    0x06, 0x00, 0xff, 0x09, 0x20, 0x75, 0x06, 0x95, 0x01, 0x15, 0x00, 0x25, 0x7f, 0x81, 0x02, 0x05,
+    0x01, 0x09, 0x33, 0x09, 0x34, 0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x02, 0x81, 0x02,
+    0x06, 0x00, 0xff, 0x09, 0x21, 0x95, 0x36, 0x81, 0x02, 0x85, 0x05, 0x09, 0x22, 0x95, 0x1f, 0x91,
+    0x02, 0x85, 0x04, 0x09, 0x23, 0x95, 0x24, 0xb1, 0x02, 0x85, 0x02, 0x09, 0x24, 0x95, 0x24, 0xb1,
+    0x02, 0x85, 0x08, 0x09, 0x25, 0x95, 0x03, 0xb1, 0x02, 0x85, 0x10, 0x09, 0x26, 0x95, 0x04, 0xb1,
+    0x02, 0x85, 0x11, 0x09, 0x27, 0x95, 0x02, 0xb1, 0x02, 0x85, 0x12, 0x06, 0x02, 0xff, 0x09, 0x21,
+    0x95, 0x0f, 0xb1, 0x02, 0x85, 0x13, 0x09, 0x22, 0x95, 0x16, 0xb1, 0x02, 0x85, 0x14, 0x06, 0x05,
+    0xff, 0x09, 0x20, 0x95, 0x10, 0xb1, 0x02, 0x85, 0x15, 0x09, 0x21, 0x95, 0x2c, 0xb1, 0x02, 0x06,
+    0x80, 0xff, 0x85, 0x80, 0x09, 0x20, 0x95, 0x06, 0xb1, 0x02, 0x85, 0x81, 0x09, 0x21, 0x95, 0x06,
+    0xb1, 0x02, 0x85, 0x82, 0x09, 0x22, 0x95, 0x05, 0xb1, 0x02, 0x85, 0x83, 0x09, 0x23, 0x95, 0x01,
+    0xb1, 0x02, 0x85, 0x84, 0x09, 0x24, 0x95, 0x04, 0xb1, 0x02, 0x85, 0x85, 0x09, 0x25, 0x95, 0x06,
+    0xb1, 0x02, 0x85, 0x86, 0x09, 0x26, 0x95, 0x06, 0xb1, 0x02, 0x85, 0x87, 0x09, 0x27, 0x95, 0x23,
+    0xb1, 0x02, 0x85, 0x88, 0x09, 0x28, 0x95, 0x22, 0xb1, 0x02, 0x85, 0x89, 0x09, 0x29, 0x95, 0x02,
+    0xb1, 0x02, 0x85, 0x90, 0x09, 0x30, 0x95, 0x05, 0xb1, 0x02, 0x85, 0x91, 0x09, 0x31, 0x95, 0x03,
+    0xb1, 0x02, 0x85, 0x92, 0x09, 0x32, 0x95, 0x03, 0xb1, 0x02, 0x85, 0x93, 0x09, 0x33, 0x95, 0x0c,
+    0xb1, 0x02, 0x85, 0x94, 0x09, 0x34, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0xa0, 0x09, 0x40, 0x95, 0x06,
+    0xb1, 0x02, 0x85, 0xa1, 0x09, 0x41, 0x95, 0x01, 0xb1, 0x02, 0x85, 0xa2, 0x09, 0x42, 0x95, 0x01,
+    0xb1, 0x02, 0x85, 0xa3, 0x09, 0x43, 0x95, 0x30, 0xb1, 0x02, 0x85, 0xa4, 0x09, 0x44, 0x95, 0x0d,
+    0xb1, 0x02, 0x85, 0xf0, 0x09, 0x47, 0x95, 0x3f, 0xb1, 0x02, 0x85, 0xf1, 0x09, 0x48, 0x95, 0x3f,
+    0xb1, 0x02, 0x85, 0xf2, 0x09, 0x49, 0x95, 0x0f, 0xb1, 0x02, 0x85, 0xa7, 0x09, 0x4a, 0x95, 0x01,
+    0xb1, 0x02, 0x85, 0xa8, 0x09, 0x4b, 0x95, 0x01, 0xb1, 0x02, 0x85, 0xa9, 0x09, 0x4c, 0x95, 0x08,
+    0xb1, 0x02, 0x85, 0xaa, 0x09, 0x4e, 0x95, 0x01, 0xb1, 0x02, 0x85, 0xab, 0x09, 0x4f, 0x95, 0x39,
+    0xb1, 0x02, 0x85, 0xac, 0x09, 0x50, 0x95, 0x39, 0xb1, 0x02, 0x85, 0xad, 0x09, 0x51, 0x95, 0x0b,
+    0xb1, 0x02, 0x85, 0xae, 0x09, 0x52, 0x95, 0x01, 0xb1, 0x02, 0x85, 0xaf, 0x09, 0x53, 0x95, 0x02,
+    0xb1, 0x02, 0x85, 0xb0, 0x09, 0x54, 0x95, 0x3f, 0xb1, 0x02, 0xc0,
+];
+
+/// A DualShock 4 on USB, as `hid-playstation` and `hid-sony` drive it.
+pub(super) struct DualShock4 {
+    mac: [u8; 6],
+    /// The input report, except for the parts that are filled in when it is
+    /// sent.
+    report: [u8; INPUT_REPORT_SIZE],
+    buttons: Buttons,
+    triggers: [u8; 2],
+    touchpad: Touchpad,
+    counter: u8,
+    start: Instant,
+}
+
+impl DualShock4 {
+    pub fn new(mac: [u8; 6]) -> Self {
+        let mut report = [0; INPUT_REPORT_SIZE];
+        report[0] = INPUT_REPORT;
+        report[1..5].copy_from_slice(&[0x80; 4]);
+        // Plugged in and fully charged.
+        report[30] = 0x10 | 10;
+
+        Self {
+            mac,
+            report,
+            buttons: Buttons::default(),
+            triggers: [0; 2],
+            touchpad: Touchpad::new(1920, 942),
+            counter: 0,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl HidProfile for DualShock4 {
+    fn name(&self) -> &str {
+        "Sony Interactive Entertainment Wireless Controller"
+    }
+
+    fn ids(&self) -> (u32, u32, u32) {
+        (VENDOR_ID, PRODUCT_ID, 0x0100)
+    }
+
+    fn descriptor(&self) -> &[u8] {
+        &DESCRIPTOR
+    }
+
+    fn apply(&mut self, r#type: u16, code: u16, value: i32) {
+        match (r#type as u32, code as u32) {
+            (EV_KEY, code) => self.buttons.apply(code, value != 0),
+            (EV_ABS, ABS_X) => self.report[1] = playstation::stick(value),
+            (EV_ABS, ABS_Y) => self.report[2] = playstation::stick(value),
+            (EV_ABS, ABS_RX) => self.report[3] = playstation::stick(value),
+            (EV_ABS, ABS_RY) => self.report[4] = playstation::stick(value),
+            (EV_ABS, ABS_Z) => self.triggers[0] = value as u8,
+            (EV_ABS, ABS_RZ) => self.triggers[1] = value as u8,
+            _ => {}
+        }
+    }
+
+    fn motion(&mut self, accel: [f32; 3], gyro: [f32; 3]) -> Result<(), Error> {
+        self.report[13..25].copy_from_slice(&playstation::motion(accel, gyro)?);
+        Ok(())
+    }
+
+    fn touch(&mut self, finger: u8, x: f32, y: f32) -> Result<(), Error> {
+        self.touchpad.touch(finger, x, y)
+    }
+
+    fn touch_up(&mut self, finger: u8) -> Result<(), Error> {
+        self.touchpad.touch_up(finger)
+    }
+
+    fn touchpad_click(&mut self, pressed: bool) -> Result<(), Error> {
+        self.buttons.set_touchpad_click(pressed);
+        Ok(())
+    }
+
+    fn release_touchpad(&mut self) {
+        self.touchpad.release();
+        self.buttons.set_touchpad_click(false);
+    }
+
+    fn set_battery(&mut self, level: u8, charging: bool) -> Result<(), Error> {
+        // The controller charges while the cable is plugged in, and tells
+        // that it is full with a capacity of 11.
+        self.report[30] = match (charging, level) {
+            (true, 100) => 0x10 | 11,
+            (true, level) => 0x10 | playstation::battery_capacity(level),
+            (false, level) => playstation::battery_capacity(level),
+        };
+        Ok(())
+    }
+
+    fn input_report(&mut self) -> Vec<u8> {
+        let mut report = self.report;
+
+        report[5..8].copy_from_slice(&self.buttons.bytes());
+        report[7] |= self.counter << 2;
+        report[8] = self.buttons.trigger(0, self.triggers[0]);
+        report[9] = self.buttons.trigger(1, self.triggers[1]);
+
+        // The sensors count time in units of 16/3 microseconds.
+        let timestamp = (self.start.elapsed().as_micros() * 3 / 16) as u16;
+        report[10..12].copy_from_slice(&timestamp.to_le_bytes());
+
+        // A single touch report with both fingers.
+        report[33] = 1;
+        report[34] = self.counter;
+        self.touchpad.write_points(&mut report[35..43]);
+
+        self.counter = (self.counter + 1) & 0x3f;
+        report.to_vec()
+    }
+
+    fn output(&mut self, report: &[u8]) -> Vec<Output> {
+        if report.len() < 11 || report[0] != OUTPUT_REPORT {
+            return Vec::new();
+        }
+
+        let flags = report[1];
+        let mut outputs = Vec::new();
+        if flags & OUTPUT_RUMBLE != 0 {
+            // The left motor is the strong one.
+            outputs.push(Output::Rumble {
+                large_motor: u16::from_be_bytes([report[5], report[5]]),
+                small_motor: u16::from_be_bytes([report[4], report[4]]),
+            });
+        }
+        if flags & OUTPUT_LIGHTBAR != 0 {
+            outputs.push(Output::Lightbar {
+                red: report[6],
+                green: report[7],
+                blue: report[8],
+            });
+        }
+        if flags & OUTPUT_LIGHTBAR_BLINK != 0 {
+            // In units of 10 milliseconds.
+            outputs.push(Output::LightbarBlink {
+                on: Duration::from_millis(report[9] as u64 * 10),
+                off: Duration::from_millis(report[10] as u64 * 10),
+            });
+        }
+        outputs
+    }
+
+    fn feature_report(&self, number: u8) -> Option<Vec<u8>> {
+        match number {
+            FEATURE_CALIBRATION => Some(playstation::calibration_report(number, 37)),
+            FEATURE_PAIRING_INFO => Some(playstation::mac_report(number, 16, self.mac)),
+            FEATURE_MAC_ADDRESS => Some(playstation::mac_report(number, 7, self.mac)),
+            FEATURE_FIRMWARE_INFO => {
+                let mut report = vec![0; 49];
+                report[0] = number;
+                // Hardware and firmware versions.
+                report[35..37].copy_from_slice(&0x0100u16.to_le_bytes());
+                report[41..43].copy_from_slice(&0x0100u16.to_le_bytes());
+                Some(report)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+
+    fn press(ds4: &mut DualShock4, code: u32, pressed: bool) {
+        ds4.apply(EV_KEY as u16, code as u16, pressed as i32);
+    }
+
+    #[test]
+    fn packs_buttons_and_the_dpad_hat() {
+        let mut ds4 = DualShock4::new(MAC);
+        assert_eq!(ds4.input_report()[5..8], [0x08, 0x00, 0x00]);
+
+        press(&mut ds4, BTN_A, true);
+        press(&mut ds4, BTN_DPAD_UP, true);
+        press(&mut ds4, BTN_DPAD_RIGHT, true);
+        press(&mut ds4, BTN_START, true);
+        press(&mut ds4, BTN_MODE, true);
+        let report = ds4.input_report();
+        assert_eq!(report[5], 0x20 | 1);
+        assert_eq!(report[6], 0x20);
+        assert_eq!(report[7] & 0x03, 0x01);
+
+        press(&mut ds4, BTN_DPAD_UP, false);
+        press(&mut ds4, BTN_A, false);
+        assert_eq!(ds4.input_report()[5], 2);
+    }
+
+    #[test]
+    fn centers_the_sticks_and_reads_trigger_buttons_as_pulled() {
+        let mut ds4 = DualShock4::new(MAC);
+        assert_eq!(ds4.input_report()[1..5], [0x80; 4]);
+
+        ds4.apply(EV_ABS as u16, ABS_X as u16, -128);
+        ds4.apply(EV_ABS as u16, ABS_RY as u16, 127);
+        ds4.apply(EV_ABS as u16, ABS_Z as u16, 100);
+        press(&mut ds4, BTN_TR2, true);
+        let report = ds4.input_report();
+        assert_eq!(report[1..5], [0x00, 0x80, 0x80, 0xff]);
+        assert_eq!(report[8..10], [100, 0xff]);
+    }
+
+    #[test]
+    fn packs_touch_points() {
+        let mut ds4 = DualShock4::new(MAC);
+        ds4.touch(1, 1.0, 1.0).unwrap();
+        let report = ds4.input_report();
+        assert_eq!(report[33], 1);
+        // 1919 by 941, twelve bits each, after the tracking id.
+        assert_eq!(report[35..43], [0x80, 0, 0, 0, 0x00, 0x7f, 0xd7, 0x3a]);
+
+        ds4.touch_up(1).unwrap();
+        assert_eq!(ds4.input_report()[39], 0x80);
+        assert!(ds4.touch(2, 0.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn decodes_rumble_and_lightbar() {
+        let mut ds4 = DualShock4::new(MAC);
+        let report = [
+            OUTPUT_REPORT,
+            OUTPUT_RUMBLE | OUTPUT_LIGHTBAR | OUTPUT_LIGHTBAR_BLINK,
+            0,
+            0,
+            0x40,
+            0xff,
+            1,
+            2,
+            3,
+            25,
+            50,
+        ];
+        let outputs = ds4.output(&report);
+        assert_eq!(outputs.len(), 3);
+        assert!(matches!(
+            outputs[0],
+            Output::Rumble {
+                large_motor: 0xffff,
+                small_motor: 0x4040,
+            }
+        ));
+        assert!(matches!(
+            outputs[1],
+            Output::Lightbar {
+                red: 1,
+                green: 2,
+                blue: 3,
+            }
+        ));
+        match outputs[2] {
+            Output::LightbarBlink { on, off } => {
+                assert_eq!(on, Duration::from_millis(250));
+                assert_eq!(off, Duration::from_millis(500));
+            }
+            ref output => panic!("Expected a blink, got {:?}", output),
+        }
+        assert!(ds4.output(&report[..10]).is_empty());
+    }
+
+    #[test]
+    fn answers_feature_reports() {
+        let ds4 = DualShock4::new(MAC);
+        assert_eq!(
+            ds4.feature_report(FEATURE_MAC_ADDRESS).unwrap(),
+            [FEATURE_MAC_ADDRESS, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]
+        );
+
+        let calibration = ds4.feature_report(FEATURE_CALIBRATION).unwrap();
+        assert_eq!(calibration.len(), 37);
+        assert_eq!(calibration[0], FEATURE_CALIBRATION);
+
+        assert!(ds4.feature_report(0x42).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use super::{
+    axis_to_binding_const, bindings::consts::*, button_to_binding_const, uhid::HidProfile,
+    PRODUCT_ID, VENDOR_ID,
+};
+use crate::api::*;
+use crate::hid_descriptor::{HidDescriptor, HidState};
+
+/// Where an event goes in the state of the device.
+#[derive(Clone, Copy)]
+enum Field {
+    Button(usize),
+    HatX(usize),
+    HatY(usize),
+    Axis(usize),
+}
+
+/// A device with the inputs and outputs of a `HidDescriptor`, which
+/// `hid-generic` drives.
+pub(super) struct GenericHid {
+    descriptor: HidDescriptor,
+    bytes: Vec<u8>,
+    /// The field of each event code, by type and code.
+    fields: HashMap<(u32, u16), Field>,
+    state: HidState,
+}
+
+impl GenericHid {
+    pub fn new(descriptor: HidDescriptor) -> Self {
+        let mut fields = HashMap::new();
+        for n in 0..descriptor.button_count() {
+            let code = button_to_binding_const(Button::Numbered(n));
+            fields.insert((EV_KEY, code), Field::Button(n as usize));
+        }
+        for (n, (x, y)) in (0..descriptor.hat_count())
+            .filter_map(Axis::hat)
+            .enumerate()
+        {
+            fields.insert((EV_ABS, axis_to_binding_const(x)), Field::HatX(n));
+            fields.insert((EV_ABS, axis_to_binding_const(y)), Field::HatY(n));
+        }
+        for (i, (axis, _)) in descriptor.axes().iter().enumerate() {
+            fields.insert((EV_ABS, axis_to_binding_const(*axis)), Field::Axis(i));
+        }
+
+        Self {
+            bytes: descriptor.to_bytes(),
+            state: descriptor.initial_state(),
+            descriptor,
+            fields,
+        }
+    }
+}
+
+impl HidProfile for GenericHid {
+    fn name(&self) -> &str {
+        "virtual HID device (vgp)"
+    }
+
+    fn ids(&self) -> (u32, u32, u32) {
+        (VENDOR_ID as u32, PRODUCT_ID as u32, 1)
+    }
+
+    fn descriptor(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn apply(&mut self, r#type: u16, code: u16, value: i32) {
+        match self.fields.get(&(r#type as u32, code)) {
+            Some(Field::Button(i)) => self.state.buttons[*i] = value != 0,
+            Some(Field::HatX(i)) => self.state.hats[*i].0 = value,
+            Some(Field::HatY(i)) => self.state.hats[*i].1 = value,
+            Some(Field::Axis(i)) => self.state.axes[*i] = value,
+            None => {}
+        }
+    }
+
+    fn set_battery(&mut self, level: u8, _charging: bool) -> Result<(), Error> {
+        if !self.descriptor.has_battery() {
+            return Err(Error::Unsupported(
+                "The descriptor of this device has no battery".to_string(),
+            ));
+        }
+        self.state.battery = level;
+        Ok(())
+    }
+
+    fn input_report(&mut self) -> Vec<u8> {
+        self.descriptor.input_report(&self.state)
+    }
+
+    fn output(&mut self, report: &[u8]) -> Vec<Output> {
+        self.descriptor.parse_output(report)
+    }
+
+    fn feature_report(&self, _number: u8) -> Option<Vec<u8>> {
+        None
+    }
+}
@@ -4,10 +4,30 @@ nix::ioctl_write_int!(ui_set_evbit, UI_IOC_MAGIC, 100);
 
 nix::ioctl_write_int!(ui_set_keybit, UI_IOC_MAGIC, 101);
 
+nix::ioctl_write_int!(ui_set_relbit, UI_IOC_MAGIC, 102);
+
 nix::ioctl_write_int!(ui_set_absbit, UI_IOC_MAGIC, 103);
 
+nix::ioctl_write_int!(ui_set_mscbit, UI_IOC_MAGIC, 104);
+
+nix::ioctl_write_int!(ui_set_ledbit, UI_IOC_MAGIC, 105);
+
 nix::ioctl_write_int!(ui_set_ffbit, UI_IOC_MAGIC, 107);
 
+// The argument is the string itself, though the request code is built from
+// the size of a pointer to it.
+nix::ioctl_write_ptr_bad!(
+    ui_set_phys,
+    nix::request_code_write!(
+        UI_IOC_MAGIC,
+        108,
+        std::mem::size_of::<*const libc::c_char>()
+    ),
+    libc::c_char
+);
+
+nix::ioctl_write_int!(ui_set_propbit, UI_IOC_MAGIC, 110);
+
 nix::ioctl_none!(ui_dev_create, UI_IOC_MAGIC, 1);
 
 nix::ioctl_none!(ui_dev_destroy, UI_IOC_MAGIC, 2);
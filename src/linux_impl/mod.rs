@@ -1,14 +1,50 @@
 mod bindings;
+mod dualsense;
+mod dualshock4;
+mod generic_hid;
 mod internal;
+mod motion;
+mod playstation;
+mod poller;
+mod switch_pro;
+mod touchpad;
 mod types;
+mod uhid;
 
-use std::collections::HashMap;
+pub use poller::{Polled, Poller};
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::{CStr, CString},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
 
 use crate::api::*;
+use crate::config::{DeviceConfig, DeviceKind};
+use crate::force_feedback::{Effect, PlayedEffect};
+use crate::input_macro::{self, InputSink, Macro, MacroHandle};
+use crate::keyboard::Layout;
+use crate::scheduler::Scheduler;
+use crate::scroll::ScrollAccumulator;
+use crate::stick::*;
+use crate::turbo::*;
+use crate::validation::*;
+use crate::watchdog::Watchdog;
 
 use bindings::consts::*;
+use dualsense::DualSense;
+use dualshock4::DualShock4;
+use generic_hid::GenericHid;
 use internal::*;
+use motion::MotionNode;
+use switch_pro::SwitchPro;
+use touchpad::TouchpadNode;
 use types::*;
+use uhid::HidProfile;
 
 use nix::{errno::Errno, fcntl::OFlag, NixPath};
 
@@ -30,218 +66,1086 @@ fn button_to_binding_const(button: Button) -> u16 {
         Button::TriggerRight2 => BTN_TR2 as u16,
         Button::ThumbStickLeft => BTN_THUMBL as u16,
         Button::ThumbStickRight => BTN_THUMBR as u16,
+        Button::Mode => BTN_MODE as u16,
+        Button::Capture => KEY_RECORD as u16,
+        Button::Paddle1 => BTN_TRIGGER_HAPPY5 as u16,
+        Button::Paddle2 => BTN_TRIGGER_HAPPY6 as u16,
+        Button::Paddle3 => BTN_TRIGGER_HAPPY7 as u16,
+        Button::Paddle4 => BTN_TRIGGER_HAPPY8 as u16,
+        // The first 16 buttons are the ones joydev and older consumers know,
+        // the rest go to the "trigger happy" range.
+        Button::Numbered(n) if n < 16 => (BTN_JOYSTICK + n as u32) as u16,
+        Button::Numbered(n) => (BTN_TRIGGER_HAPPY1 + (n - 16) as u32) as u16,
+        Button::Key(key) => key.0,
+        Button::MouseLeft => BTN_LEFT as u16,
+        Button::MouseRight => BTN_RIGHT as u16,
+        Button::MouseMiddle => BTN_MIDDLE as u16,
+        Button::MouseSide => BTN_SIDE as u16,
+        Button::MouseExtra => BTN_EXTRA as u16,
     }
 }
 
+fn axis_to_binding_const(axis: Axis) -> u16 {
+    match axis {
+        Axis::X => ABS_X as u16,
+        Axis::Y => ABS_Y as u16,
+        Axis::Z => ABS_Z as u16,
+        Axis::Rx => ABS_RX as u16,
+        Axis::Ry => ABS_RY as u16,
+        Axis::Rz => ABS_RZ as u16,
+        Axis::Throttle => ABS_THROTTLE as u16,
+        Axis::Rudder => ABS_RUDDER as u16,
+        Axis::Wheel => ABS_WHEEL as u16,
+        Axis::Gas => ABS_GAS as u16,
+        Axis::Brake => ABS_BRAKE as u16,
+        Axis::Hat0X => ABS_HAT0X as u16,
+        Axis::Hat0Y => ABS_HAT0Y as u16,
+        Axis::Hat1X => ABS_HAT1X as u16,
+        Axis::Hat1Y => ABS_HAT1Y as u16,
+        Axis::Hat2X => ABS_HAT2X as u16,
+        Axis::Hat2Y => ABS_HAT2Y as u16,
+        Axis::Hat3X => ABS_HAT3X as u16,
+        Axis::Hat3Y => ABS_HAT3Y as u16,
+        Axis::Pressure => ABS_PRESSURE as u16,
+        Axis::Distance => ABS_DISTANCE as u16,
+        Axis::TiltX => ABS_TILT_X as u16,
+        Axis::TiltY => ABS_TILT_Y as u16,
+        Axis::ToolWidth => ABS_TOOL_WIDTH as u16,
+        Axis::Volume => ABS_VOLUME as u16,
+        Axis::Misc => ABS_MISC as u16,
+    }
+}
+
+const VENDOR_ID: u16 = 0x0bdc;
+const PRODUCT_ID: u16 = 0x4386;
+
+const THUMB_STICK_AXIS_INFO: AxisInfo = AxisInfo {
+    minimum: -512,
+    maximum: 512,
+    fuzz: 0,
+    flat: 15,
+    resolution: 0,
+};
+
+/// Sets `code` up as an absolute axis with the range of `info`.
+unsafe fn set_up_abs(fd: i32, code: u16, info: &AxisInfo) -> Result<(), Error> {
+    let abs_setup = AbsSetup::from(SafeAbsSetup {
+        code,
+        value: 0,
+        minimum: info.minimum,
+        maximum: info.maximum,
+        fuzz: info.fuzz,
+        flat: info.flat,
+        resolution: info.resolution,
+    });
+    ui_set_absbit(fd, code as u64).map_with_vgp_error()?;
+    ui_abs_setup(fd, abs_setup.const_ptr()).map_with_vgp_error()?;
+    Ok(())
+}
+
 struct UInputFD(i32);
 
 impl UInputFD {
+    const CLOSED: i32 = -1;
+
     fn new() -> Result<Self, nix::Error> {
-        "/dev/uinput"
-            .with_nix_path(|p| {
-                let flags = OFlag::O_RDWR | OFlag::O_NONBLOCK;
-                let fd = unsafe { libc::open(p.as_ptr(), flags.bits()) };
-                Errno::result(fd)
-            })?
-            .map(|fd| UInputFD(fd))
+        Self::open("/dev/uinput")
+    }
+
+    fn open(path: &str) -> Result<Self, nix::Error> {
+        path.with_nix_path(|p| {
+            let flags = OFlag::O_RDWR | OFlag::O_NONBLOCK;
+            let fd = unsafe { libc::open(p.as_ptr(), flags.bits()) };
+            Errno::result(fd)
+        })?
+        .map(|fd| UInputFD(fd))
+    }
+
+    fn close(&mut self) -> Result<(), nix::Error> {
+        // The descriptor is released even if closing it fails, so it must not
+        // be closed again on drop.
+        let fd = std::mem::replace(&mut self.0, Self::CLOSED);
+        nix::unistd::close(fd)
     }
 }
 
 impl Drop for UInputFD {
     fn drop(&mut self) {
+        if self.0 == Self::CLOSED {
+            return;
+        }
         if let Err(e) = nix::unistd::close(self.0) {
             log::error!("Failed to close device's file descriptor: {:?}", e);
         }
     }
 }
 
-pub struct Bus;
+/// The device's file descriptor, shared with the threads that write inputs on
+/// the device's behalf. The device is destroyed when the last one is dropped,
+/// unless that was done explicitly before.
+struct DeviceFD {
+    fd: UInputFD,
+    /// The profile of a device that is emulated through uhid. Events go into
+    /// its input report instead of being written as they are.
+    hid: Option<Mutex<Box<dyn HidProfile>>>,
+    report_lock: Mutex<()>,
+    destroyed: AtomicBool,
+    /// Whether the caps lock LED was last turned on, as read from the outputs.
+    caps_lock: AtomicBool,
+}
+
+impl DeviceFD {
+    fn new(fd: UInputFD) -> Self {
+        Self {
+            fd,
+            hid: None,
+            report_lock: Mutex::new(()),
+            destroyed: AtomicBool::new(false),
+            caps_lock: AtomicBool::new(false),
+        }
+    }
+
+    fn new_hid(fd: UInputFD, profile: Box<dyn HidProfile>) -> Self {
+        Self {
+            fd,
+            hid: Some(Mutex::new(profile)),
+            report_lock: Mutex::new(()),
+            destroyed: AtomicBool::new(false),
+            caps_lock: AtomicBool::new(false),
+        }
+    }
+
+    fn raw(&self) -> i32 {
+        self.fd.0
+    }
+
+    fn is_destroyed(&self) -> bool {
+        self.destroyed.load(Ordering::SeqCst)
+    }
+
+    fn destroy(&self) -> Result<(), Error> {
+        // The report lock keeps the flag from changing between the check and
+        // the destroy. It is only set once the device is gone, so a destroy
+        // that failed can be tried again, on drop at the latest.
+        let _report_lock = self.report_lock.lock().unwrap();
+        if self.is_destroyed() {
+            return Ok(());
+        }
+
+        if self.hid.is_some() {
+            uhid::destroy(self.raw())?;
+        } else {
+            unsafe {
+                ui_dev_destroy(self.raw()).map_with_vgp_error()?;
+            }
+        }
+        self.destroyed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Destroys the device and closes its file descriptor.
+    fn close(mut self) -> Result<(), Error> {
+        self.destroy()?;
+        self.fd.close().map_with_vgp_error()
+    }
+
+    /// Writes `events` followed by a `SYN_REPORT`. Reports written from
+    /// different threads never interleave.
+    fn write_report(&self, events: &[(u16, u16, i32)]) -> Result<(), Error> {
+        let time_now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| Error::Unknown(format!("Cannot get system time! {}", e)))?;
+        let time = (time_now.as_secs() as i64, time_now.subsec_micros() as i64);
+
+        let _report_lock = self.report_lock.lock().unwrap();
+        if self.is_destroyed() {
+            return Err(Error::Unplugged);
+        }
+
+        if let Some(hid) = &self.hid {
+            let mut hid = hid.lock().unwrap();
+            for (r#type, code, value) in events.iter() {
+                hid.apply(*r#type, *code, *value);
+            }
+            return uhid::write_input(self.raw(), &hid.input_report());
+        }
+
+        let syn_report = (EV_SYN as u16, SYN_REPORT as u16, 0);
+        for (r#type, code, value) in events.iter().chain(std::iter::once(&syn_report)) {
+            let input_event = InputEvent::from(SafeInputEvent {
+                r#type: *r#type,
+                code: *code,
+                value: *value,
+                time,
+            });
+            unsafe {
+                let n = libc::write(self.raw(), input_event.c_ptr(), InputEvent::size());
+                Errno::result(n).map_with_vgp_error()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies `update` to the input report of a device that is emulated
+    /// through uhid and sends the report. Returns `None` for other devices.
+    fn update_hid(
+        &self,
+        update: impl FnOnce(&mut dyn HidProfile) -> Result<(), Error>,
+    ) -> Option<Result<(), Error>> {
+        let hid = self.hid.as_ref()?;
+
+        let _report_lock = self.report_lock.lock().unwrap();
+        if self.is_destroyed() {
+            return Some(Err(Error::Unplugged));
+        }
+
+        let mut hid = hid.lock().unwrap();
+        Some(update(&mut **hid).and_then(|_| uhid::write_input(self.raw(), &hid.input_report())))
+    }
+}
+
+impl Drop for DeviceFD {
+    fn drop(&mut self) {
+        if let Err(e) = self.destroy() {
+            log::error!("Failed to destroy device: {:?}", e);
+        }
+    }
+}
+
+struct Slot {
+    fd: Weak<DeviceFD>,
+    input: Weak<DeviceInput>,
+}
+
+impl Slot {
+    fn is_plugged_in(&self) -> bool {
+        match self.fd.upgrade() {
+            Some(fd) => !fd.is_destroyed(),
+            None => false,
+        }
+    }
+}
+
+/// The devices a bus has plugged in, by their slot.
+#[derive(Default)]
+struct Registry {
+    slots: Vec<Option<Slot>>,
+    max_devices: Option<usize>,
+}
+
+impl Registry {
+    fn plugged_in(&self) -> impl Iterator<Item = (DeviceId, &Slot)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Some(slot) if slot.is_plugged_in() => Some((DeviceId(index), slot)),
+                _ => None,
+            })
+    }
+
+    /// The lowest slot that is free, unless the maximum number of devices is
+    /// already plugged in.
+    fn free_slot(&self) -> Option<usize> {
+        if let Some(max_devices) = self.max_devices {
+            if self.plugged_in().count() >= max_devices {
+                return None;
+            }
+        }
+
+        let free = self.slots.iter().position(|slot| match slot {
+            Some(slot) => !slot.is_plugged_in(),
+            None => true,
+        });
+        Some(free.unwrap_or(self.slots.len()))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Bus {
+    registry: Arc<Mutex<Registry>>,
+}
 
 impl Bus {
     pub fn new() -> Result<Self, Error> {
-        Ok(Self)
+        Ok(Self::default())
+    }
+
+    /// Limits how many devices this bus keeps plugged in at once. `plug_in`
+    /// fails with `Error::DeviceLimitReached` past that.
+    pub fn set_max_devices(&mut self, max_devices: Option<usize>) {
+        self.registry.lock().unwrap().max_devices = max_devices;
+    }
+
+    pub fn devices(&self) -> Vec<DeviceId> {
+        let registry = self.registry.lock().unwrap();
+        registry.plugged_in().map(|(id, _)| id).collect()
+    }
+
+    pub fn device(&self, id: DeviceId) -> Option<InputHandle> {
+        let registry = self.registry.lock().unwrap();
+        let (_, slot) = registry.plugged_in().find(|(slot_id, _)| *slot_id == id)?;
+        slot.input.upgrade().map(|input| InputHandle { id, input })
+    }
+
+    /// Unplugs every device of this bus, even the ones that are still owned
+    /// somewhere. Putting inputs on them fails with `Error::Unplugged`
+    /// afterwards.
+    pub fn unplug_all(&mut self) -> Result<(), Error> {
+        let mut registry = self.registry.lock().unwrap();
+
+        let mut result = Ok(());
+        for slot in registry.slots.drain(..).flatten() {
+            let companions = slot
+                .input
+                .upgrade()
+                .map(|input| input.companion_fds())
+                .unwrap_or_default();
+            for fd in slot.fd.upgrade().into_iter().chain(companions) {
+                if let Err(e) = fd.destroy() {
+                    log::error!("Failed to destroy device: {:?}", e);
+                    result = result.and(Err(e));
+                }
+            }
+        }
+        result
     }
 
     pub fn plug_in(&mut self) -> Result<Device, Error> {
-        let fd = UInputFD::new().map_with_vgp_error()?;
-
-        let abs_x_setup = AbsSetup::from(SafeAbsSetup {
-            code: ABS_X as u16,
-            value: 0,
-            minimum: -512,
-            maximum: 512,
-            fuzz: 0,
-            flat: 15,
-            resolution: 0,
-        });
-        let abs_y_setup = AbsSetup::from(SafeAbsSetup {
-            code: ABS_Y as u16,
-            value: 0,
-            minimum: -512,
-            maximum: 512,
-            fuzz: 0,
-            flat: 15,
-            resolution: 0,
-        });
-        let abs_rx_setup = AbsSetup::from(SafeAbsSetup {
-            code: ABS_RX as u16,
-            value: 0,
-            minimum: -512,
-            maximum: 512,
-            fuzz: 0,
-            flat: 15,
-            resolution: 0,
-        });
-        let abs_ry_setup = AbsSetup::from(SafeAbsSetup {
-            code: ABS_RY as u16,
-            value: 0,
-            minimum: -512,
-            maximum: 512,
-            fuzz: 0,
-            flat: 15,
-            resolution: 0,
+        self.plug_in_with(&DeviceConfig::default())
+    }
+
+    /// Plugs in a device as `config` describes it. Its motion sensors and
+    /// touchpad are nodes of their own, which consumers can only match with
+    /// the device by their physical path, see `MotionConfig`.
+    pub fn plug_in_with(&mut self, config: &DeviceConfig) -> Result<Device, Error> {
+        config.validate().map_err(Error::Unsupported)?;
+
+        let mut registry = self.registry.lock().unwrap();
+        let slot = registry.free_slot().ok_or(Error::DeviceLimitReached)?;
+
+        // All nodes of a device share their physical path, so consumers that
+        // look at it can tell that they belong together. uinput has no way to
+        // give them the same uniq, which is what SDL looks at instead.
+        let phys = CString::new(format!("vgp-{}/device{}", std::process::id(), slot)).unwrap();
+
+        let buttons = config.buttons();
+        let axes = config.axes(THUMB_STICK_AXIS_INFO);
+        let mac = device_mac(slot);
+        let (fd, pending) = match hid_profile(&config.kind, mac) {
+            Some(profile) => {
+                let uniq = mac
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(":");
+                uhid::create(profile, &phys, &uniq)?
+            }
+            None => (
+                Arc::new(create_uinput(config, &buttons, &axes, &phys)?),
+                VecDeque::new(),
+            ),
+        };
+
+        let motion = match &config.motion {
+            Some(motion) => Some(MotionNode::new(motion, &phys)?),
+            None => None,
+        };
+        let touchpad = match &config.touchpad {
+            Some(touchpad) => Some(TouchpadNode::new(touchpad, &phys)?),
+            None => None,
+        };
+
+        let input = Arc::new(DeviceInput {
+            fd: Arc::clone(&fd),
+            buttons,
+            axes,
+            config: Mutex::new(InputConfig::default()),
+            turbo: Mutex::new(None),
+            held: Mutex::new(HashSet::new()),
+            watchdog: Mutex::new(None),
+            motion,
+            touchpad,
+            layout: match &config.kind {
+                DeviceKind::Keyboard(keyboard) => Some(keyboard.layout),
+                _ => None,
+            },
+            scroll: match config.kind {
+                DeviceKind::Mouse => Some(Mutex::new(ScrollAccumulator::default())),
+                _ => None,
+            },
+            pointer: matches!(config.kind, DeviceKind::Pointer(_)),
+            pen: matches!(&config.kind, DeviceKind::Pointer(pointer) if pointer.pen),
+            touching: AtomicBool::new(false),
         });
-        let setup = Setup::from(SafeSetup {
-            bustype: 0x06,
-            version: 1,
-            vendor: 0x0bdc,
-            product: 0x4386,
-            ff_effects_max: FF_MAX_EFFECTS as u32,
-            name: "virtual gamepad (vgp)\0",
+
+        if registry.slots.len() <= slot {
+            registry.slots.resize_with(slot + 1, || None);
+        }
+        registry.slots[slot] = Some(Slot {
+            fd: Arc::downgrade(&fd),
+            input: Arc::downgrade(&input),
         });
 
-        unsafe {
-            ui_set_evbit(fd.0, EV_KEY as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_DPAD_UP as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_DPAD_DOWN as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_DPAD_LEFT as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_DPAD_RIGHT as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_X as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_Y as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_A as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_B as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_START as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_SELECT as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_THUMBL as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_THUMBR as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_TL as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_TL2 as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_TR as u64).map_with_vgp_error()?;
-            ui_set_keybit(fd.0, BTN_TR2 as u64).map_with_vgp_error()?;
+        let id = DeviceId(slot);
+        Ok(Device {
+            input: InputHandle { id, input },
+            output: OutputHandle {
+                id,
+                output: Arc::new(DeviceOutput {
+                    fd,
+                    ff_map: Mutex::new(HashMap::new()),
+                    pending: Mutex::new(pending),
+                }),
+            },
+        })
+    }
+}
+
+/// Creates the uinput node of a device with `buttons` and `axes`.
+fn create_uinput(
+    config: &DeviceConfig,
+    buttons: &HashSet<Button>,
+    axes: &HashMap<Axis, AxisInfo>,
+    phys: &CStr,
+) -> Result<DeviceFD, Error> {
+    let fd = UInputFD::new().map_with_vgp_error()?;
+
+    let (name, force_feedback): (_, &[u32]) = match config.kind {
+        DeviceKind::Gamepad => ("virtual gamepad (vgp)\0", &[FF_RUMBLE]),
+        DeviceKind::Joystick(_) => ("virtual joystick (vgp)\0", &[]),
+        DeviceKind::Wheel(_) => (
+            "virtual racing wheel (vgp)\0",
+            &[FF_CONSTANT, FF_SPRING, FF_DAMPER, FF_GAIN, FF_AUTOCENTER],
+        ),
+        DeviceKind::Flight(_) => ("virtual flight stick (vgp)\0", &[]),
+        DeviceKind::Keyboard(_) => ("virtual keyboard (vgp)\0", &[]),
+        DeviceKind::Mouse => ("virtual mouse (vgp)\0", &[]),
+        DeviceKind::Pointer(_) => ("virtual pointer (vgp)\0", &[]),
+        DeviceKind::DualShock4
+        | DeviceKind::DualSense
+        | DeviceKind::SwitchPro
+        | DeviceKind::Hid(_) => unreachable!("This device is emulated through uhid"),
+    };
 
+    let setup = Setup::from(SafeSetup {
+        bustype: 0x06,
+        version: 1,
+        vendor: VENDOR_ID,
+        product: PRODUCT_ID,
+        ff_effects_max: FF_MAX_EFFECTS as u32,
+        name,
+    });
+
+    unsafe {
+        ui_set_evbit(fd.0, EV_KEY as u64).map_with_vgp_error()?;
+        for button in buttons.iter() {
+            ui_set_keybit(fd.0, button_to_binding_const(*button) as u64).map_with_vgp_error()?;
+        }
+
+        match &config.kind {
+            DeviceKind::Mouse => {
+                ui_set_evbit(fd.0, EV_REL as u64).map_with_vgp_error()?;
+                for rel in [
+                    REL_X,
+                    REL_Y,
+                    REL_WHEEL,
+                    REL_HWHEEL,
+                    REL_WHEEL_HI_RES,
+                    REL_HWHEEL_HI_RES,
+                ]
+                .iter()
+                {
+                    ui_set_relbit(fd.0, *rel as u64).map_with_vgp_error()?;
+                }
+            }
+            DeviceKind::Pointer(pointer) => {
+                // A drawing tablet moves the pointer across the screen rather
+                // than being the screen itself.
+                let property = if pointer.pen {
+                    INPUT_PROP_POINTER
+                } else {
+                    INPUT_PROP_DIRECT
+                };
+                ui_set_propbit(fd.0, property as u64).map_with_vgp_error()?;
+                ui_set_keybit(fd.0, BTN_TOUCH as u64).map_with_vgp_error()?;
+                if pointer.pen {
+                    ui_set_keybit(fd.0, BTN_TOOL_PEN as u64).map_with_vgp_error()?;
+                }
+            }
+            _ => {}
+        }
+
+        if !force_feedback.is_empty() {
             ui_set_evbit(fd.0, EV_FF as u64).map_with_vgp_error()?;
-            ui_set_ffbit(fd.0, FF_RUMBLE as u64).map_with_vgp_error()?;
+            for ff in force_feedback.iter() {
+                ui_set_ffbit(fd.0, *ff as u64).map_with_vgp_error()?;
+            }
+        }
 
+        if !axes.is_empty() {
             ui_set_evbit(fd.0, EV_ABS as u64).map_with_vgp_error()?;
-            ui_set_absbit(fd.0, ABS_X as u64).map_with_vgp_error()?;
-            ui_set_absbit(fd.0, ABS_RX as u64).map_with_vgp_error()?;
-            ui_set_absbit(fd.0, ABS_Y as u64).map_with_vgp_error()?;
-            ui_set_absbit(fd.0, ABS_RY as u64).map_with_vgp_error()?;
-
-            ui_abs_setup(fd.0, abs_x_setup.const_ptr()).map_with_vgp_error()?;
-            ui_abs_setup(fd.0, abs_y_setup.const_ptr()).map_with_vgp_error()?;
-            ui_abs_setup(fd.0, abs_rx_setup.const_ptr()).map_with_vgp_error()?;
-            ui_abs_setup(fd.0, abs_ry_setup.const_ptr()).map_with_vgp_error()?;
+            for (axis, info) in axes.iter() {
+                set_up_abs(fd.0, axis_to_binding_const(*axis), info)?;
+            }
+        }
 
-            ui_dev_setup(fd.0, setup.const_ptr()).map_with_vgp_error()?;
-            ui_dev_create(fd.0).map_with_vgp_error()?;
+        let mut leds: Vec<u32> = config.leds.iter().map(|led| *led as u32).collect();
+        if let DeviceKind::Keyboard(_) = config.kind {
+            leds.extend_from_slice(&[LED_NUML, LED_CAPSL, LED_SCROLLL]);
+        }
+        if !leds.is_empty() {
+            ui_set_evbit(fd.0, EV_LED as u64).map_with_vgp_error()?;
+            for led in leds.iter() {
+                ui_set_ledbit(fd.0, *led as u64).map_with_vgp_error()?;
+            }
         }
 
-        Ok(Device {
-            fd,
-            ff_map: HashMap::new(),
-        })
+        ui_set_phys(fd.0, phys.as_ptr()).map_with_vgp_error()?;
+        ui_dev_setup(fd.0, setup.const_ptr()).map_with_vgp_error()?;
+        ui_dev_create(fd.0).map_with_vgp_error()?;
     }
+
+    Ok(DeviceFD::new(fd))
 }
 
-pub struct Device {
-    fd: UInputFD,
-    ff_map: HashMap<u32, ForceFeedback>,
+/// A locally administered MAC address, unique to the process and the slot of
+/// the device, for devices that report one.
+fn device_mac(slot: usize) -> [u8; 6] {
+    let pid = std::process::id().to_be_bytes();
+    [0x02, pid[0], pid[1], pid[2], pid[3], slot as u8]
 }
 
-impl Device {
-    pub fn put_input(&mut self, input: Input) -> Result<(), Error> {
-        let time_now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|e| Error::Unknown(format!("Cannot get system time! {}", e)))?;
-        let time = (time_now.as_secs() as i64, time_now.subsec_micros() as i64);
+/// The HID device that emulates a device of `kind`, if it is not a uinput
+/// device.
+fn hid_profile(kind: &DeviceKind, mac: [u8; 6]) -> Option<Box<dyn HidProfile>> {
+    match kind {
+        DeviceKind::DualShock4 => Some(Box::new(DualShock4::new(mac))),
+        DeviceKind::DualSense => Some(Box::new(DualSense::new(mac))),
+        DeviceKind::SwitchPro => Some(Box::new(SwitchPro::new(mac))),
+        DeviceKind::Hid(descriptor) => Some(Box::new(GenericHid::new(descriptor.clone()))),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct InputConfig {
+    stick_processors: StickProcessors,
+    value_policy: ValuePolicy,
+}
+
+/// The input side of a device, shared with the threads that put inputs on the
+/// device's behalf.
+struct DeviceInput {
+    fd: Arc<DeviceFD>,
+    buttons: HashSet<Button>,
+    axes: HashMap<Axis, AxisInfo>,
+    config: Mutex<InputConfig>,
+    turbo: Mutex<Option<TurboWorker>>,
+    /// The buttons that were pressed and not released yet, including the ones
+    /// whose autofire is on.
+    held: Mutex<HashSet<Button>>,
+    watchdog: Mutex<Option<Watchdog>>,
+    motion: Option<MotionNode>,
+    touchpad: Option<TouchpadNode>,
+    /// The layout of a keyboard.
+    layout: Option<Layout>,
+    /// The scrolling of a mouse.
+    scroll: Option<Mutex<ScrollAccumulator>>,
+    /// Whether the device is a touch surface itself, which finger 0 touches.
+    pointer: bool,
+    /// Whether the touch surface is touched with a pen.
+    pen: bool,
+    /// Whether finger 0 is on the touch surface.
+    touching: AtomicBool,
+}
 
-        match input {
+impl DeviceInput {
+    /// The nodes plugged in next to the device, like its motion sensors.
+    fn companion_fds(&self) -> Vec<Arc<DeviceFD>> {
+        let motion = self.motion.as_ref().map(|motion| &motion.fd);
+        let touchpad = self.touchpad.as_ref().map(|touchpad| &touchpad.fd);
+        motion.into_iter().chain(touchpad).map(Arc::clone).collect()
+    }
+
+    fn axis_info_or_err(&self, axis: Axis) -> Result<AxisInfo, Error> {
+        self.axes
+            .get(&axis)
+            .copied()
+            .ok_or(Error::InvalidInput(InvalidInput::UnsupportedAxis(axis)))
+    }
+
+    fn check_button(&self, button: Button) -> Result<(), Error> {
+        if self.buttons.contains(&button) {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(InvalidInput::UnsupportedButton(button)))
+        }
+    }
+
+    /// Puts an input that only goes to a node next to the device, or into the
+    /// input report of a device emulated through uhid. Fails with `missing`
+    /// if the device has neither.
+    fn put_on_node<N>(
+        &self,
+        node: &Option<N>,
+        missing: InvalidInput,
+        put: impl FnOnce(&N) -> Result<(), Error>,
+        update: impl FnOnce(&mut dyn HidProfile) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        match node {
+            Some(node) => put(node),
+            None => self
+                .fd
+                .update_hid(update)
+                .unwrap_or(Err(Error::InvalidInput(missing))),
+        }
+    }
+
+    fn check_pointer_finger(&self, finger: u8) -> Result<(), Error> {
+        if finger == 0 {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(InvalidInput::UnsupportedFinger(finger)))
+        }
+    }
+
+    /// The events that put the pointer on a touch surface at `x` and `y`.
+    /// A touch starts at full pressure, which `Axis::Pressure` changes while
+    /// it lasts.
+    fn touch_pointer(&self, x: f32, y: f32) -> Result<Vec<(u16, u16, i32)>, Error> {
+        let x = TouchpadNode::position(x, &self.axis_info_or_err(Axis::X)?)?;
+        let y = TouchpadNode::position(y, &self.axis_info_or_err(Axis::Y)?)?;
+
+        let mut events = vec![
+            (EV_ABS as u16, ABS_X as u16, x),
+            (EV_ABS as u16, ABS_Y as u16, y),
+        ];
+        if !self.touching.swap(true, Ordering::SeqCst) {
+            if let Some(pressure) = self.axes.get(&Axis::Pressure) {
+                events.push((EV_ABS as u16, ABS_PRESSURE as u16, pressure.maximum));
+            }
+            if self.pen {
+                events.push((EV_KEY as u16, BTN_TOOL_PEN as u16, 1));
+            }
+            events.push((EV_KEY as u16, BTN_TOUCH as u16, 1));
+        }
+        Ok(events)
+    }
+
+    /// The events that lift the pointer off a touch surface.
+    fn lift_pointer(&self) -> Vec<(u16, u16, i32)> {
+        self.touching.store(false, Ordering::SeqCst);
+
+        let mut events = vec![(EV_KEY as u16, BTN_TOUCH as u16, 0)];
+        if self.pen {
+            events.push((EV_KEY as u16, BTN_TOOL_PEN as u16, 0));
+        }
+        if self.axes.contains_key(&Axis::Pressure) {
+            events.push((EV_ABS as u16, ABS_PRESSURE as u16, 0));
+        }
+        events
+    }
+
+    fn set_turbo(&self, button: Button, turbo: Option<Turbo>) -> Result<(), Error> {
+        let mut worker = self.turbo.lock().unwrap();
+        if worker.is_none() {
+            if turbo.is_none() {
+                return Ok(());
+            }
+
+            let fd = Arc::clone(&self.fd);
+            *worker = Some(TurboWorker::new(Arc::new(move |button, pressed| {
+                fd.write_report(&[(
+                    EV_KEY as u16,
+                    button_to_binding_const(button),
+                    pressed as i32,
+                )])
+            })));
+        }
+
+        worker.as_ref().unwrap().set(button, turbo)
+    }
+
+    fn put_input(&self, input: Input) -> Result<(), Error> {
+        if let Some(watchdog) = &*self.watchdog.lock().unwrap() {
+            watchdog.feed();
+        }
+
+        let events = match input {
             Input::Press(button) => {
-                let press_input_event = InputEvent::from(SafeInputEvent {
-                    r#type: EV_KEY as u16,
-                    code: button_to_binding_const(button),
-                    value: 1,
-                    time,
-                });
-                unsafe {
-                    let n = libc::write(self.fd.0, press_input_event.c_ptr(), InputEvent::size());
-                    Errno::result(n).map_with_vgp_error()?;
+                self.check_button(button)?;
+                self.held.lock().unwrap().insert(button);
+                if let Some(turbo) = &*self.turbo.lock().unwrap() {
+                    if turbo.press(button)? {
+                        return Ok(());
+                    }
                 }
+                vec![(EV_KEY as u16, button_to_binding_const(button), 1)]
             }
             Input::Release(button) => {
-                let release_input_event = InputEvent::from(SafeInputEvent {
-                    r#type: EV_KEY as u16,
-                    code: button_to_binding_const(button),
-                    value: 0,
-                    time,
-                });
-                unsafe {
-                    let n = libc::write(self.fd.0, release_input_event.c_ptr(), InputEvent::size());
-                    Errno::result(n).map_with_vgp_error()?;
+                self.check_button(button)?;
+                self.held.lock().unwrap().remove(&button);
+                if let Some(turbo) = &*self.turbo.lock().unwrap() {
+                    if turbo.release(button)? {
+                        return Ok(());
+                    }
                 }
+                vec![(EV_KEY as u16, button_to_binding_const(button), 0)]
             }
             Input::Move { thumb_stick, x, y } => {
-                let x_change_input_event = InputEvent::from(SafeInputEvent {
-                    r#type: EV_ABS as u16,
-                    code: match thumb_stick {
-                        ThumbStick::Left => ABS_X as u16,
-                        ThumbStick::Right => ABS_RX as u16,
-                    },
-                    value: (x * 512f32) as i32,
-                    time,
-                });
-                unsafe {
-                    let n =
-                        libc::write(self.fd.0, x_change_input_event.c_ptr(), InputEvent::size());
-                    Errno::result(n).map_with_vgp_error()?;
+                let (x, y) = {
+                    let config = self.config.lock().unwrap();
+                    let (x, y) = config
+                        .value_policy
+                        .apply(x, y)
+                        .map_err(Error::InvalidInput)?;
+                    config.stick_processors.get(thumb_stick).apply(x, y)
+                };
+
+                let (x_axis, y_axis) = thumb_stick.axes();
+                let x = self.axis_info_or_err(x_axis)?.scale(x);
+                let y = self.axis_info_or_err(y_axis)?.scale(y);
+
+                vec![
+                    (EV_ABS as u16, axis_to_binding_const(x_axis), x),
+                    (EV_ABS as u16, axis_to_binding_const(y_axis), y),
+                ]
+            }
+            Input::Axis { axis, value } => {
+                let info = self.axis_info_or_err(axis)?;
+                let value = self
+                    .config
+                    .lock()
+                    .unwrap()
+                    .value_policy
+                    .apply_raw(axis, value, &info)
+                    .map_err(Error::InvalidInput)?;
+
+                vec![(EV_ABS as u16, axis_to_binding_const(axis), value)]
+            }
+            Input::Motion { accel, gyro } => {
+                return self.put_on_node(
+                    &self.motion,
+                    InvalidInput::NoMotionSensors,
+                    |motion| motion.put(accel, gyro),
+                    |hid| hid.motion(accel, gyro),
+                );
+            }
+            Input::Touch { finger, x, y } if self.pointer => {
+                self.check_pointer_finger(finger)?;
+                self.touch_pointer(x, y)?
+            }
+            Input::TouchUp { finger } if self.pointer => {
+                self.check_pointer_finger(finger)?;
+                self.lift_pointer()
+            }
+            Input::Touch { finger, x, y } => {
+                return self.put_on_node(
+                    &self.touchpad,
+                    InvalidInput::NoTouchpad,
+                    |touchpad| touchpad.touch(finger, x, y),
+                    |hid| hid.touch(finger, x, y),
+                );
+            }
+            Input::TouchUp { finger } => {
+                return self.put_on_node(
+                    &self.touchpad,
+                    InvalidInput::NoTouchpad,
+                    |touchpad| touchpad.touch_up(finger),
+                    |hid| hid.touch_up(finger),
+                );
+            }
+            Input::TouchpadClick { pressed } => {
+                return self.put_on_node(
+                    &self.touchpad,
+                    InvalidInput::NoTouchpad,
+                    |touchpad| touchpad.click(pressed),
+                    |hid| hid.touchpad_click(pressed),
+                );
+            }
+            Input::MouseMove { x, y } => {
+                self.scroll
+                    .as_ref()
+                    .ok_or(Error::InvalidInput(InvalidInput::NotAMouse))?;
+
+                let mut events = vec![
+                    (EV_REL as u16, REL_X as u16, x),
+                    (EV_REL as u16, REL_Y as u16, y),
+                ];
+                events.retain(|(_, _, value)| *value != 0);
+                events
+            }
+            Input::Scroll {
+                vertical,
+                horizontal,
+            } => {
+                let scroll = self
+                    .scroll
+                    .as_ref()
+                    .ok_or(Error::InvalidInput(InvalidInput::NotAMouse))?;
+                let [notches, horizontal_notches] =
+                    scroll.lock().unwrap().scroll(vertical, horizontal);
+
+                // Consumers that know the high-resolution wheels ignore the
+                // others, and the other way around.
+                let mut events = vec![
+                    (EV_REL as u16, REL_WHEEL_HI_RES as u16, vertical),
+                    (EV_REL as u16, REL_HWHEEL_HI_RES as u16, horizontal),
+                    (EV_REL as u16, REL_WHEEL as u16, notches),
+                    (EV_REL as u16, REL_HWHEEL as u16, horizontal_notches),
+                ];
+                events.retain(|(_, _, value)| *value != 0);
+                events
+            }
+        };
+        // Moving or scrolling by nothing leaves nothing to report.
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        self.fd.write_report(&events)
+    }
+
+    /// Releases every held button and centers every axis in a single report.
+    /// Buttons with autofire are released by their turbo worker. Fingers on
+    /// the touchpad are lifted in a report of the touchpad's own.
+    fn reset(&self) -> Result<(), Error> {
+        let held: Vec<Button> = self.held.lock().unwrap().drain().collect();
+
+        let mut events = Vec::new();
+        {
+            let turbo = self.turbo.lock().unwrap();
+            for button in held {
+                if let Some(turbo) = &*turbo {
+                    if turbo.release(button)? {
+                        continue;
+                    }
                 }
-                let y_change_input_event = InputEvent::from(SafeInputEvent {
-                    r#type: EV_ABS as u16,
-                    code: match thumb_stick {
-                        ThumbStick::Left => ABS_Y as u16,
-                        ThumbStick::Right => ABS_RY as u16,
-                    },
-                    value: (y * 512f32) as i32,
-                    time,
-                });
-                unsafe {
-                    let n =
-                        libc::write(self.fd.0, y_change_input_event.c_ptr(), InputEvent::size());
-                    Errno::result(n).map_with_vgp_error()?;
+                events.push((EV_KEY as u16, button_to_binding_const(button), 0));
+            }
+        }
+        events.extend(
+            self.axes
+                .iter()
+                .map(|(axis, info)| (EV_ABS as u16, axis_to_binding_const(*axis), info.neutral())),
+        );
+        if self.pointer {
+            events.extend(self.lift_pointer());
+        }
+
+        let result = self.fd.write_report(&events);
+        let touchpad = match &self.touchpad {
+            Some(touchpad) => touchpad.reset(),
+            None => self
+                .fd
+                .update_hid(|hid| {
+                    hid.release_touchpad();
+                    Ok(())
+                })
+                .unwrap_or(Ok(())),
+        };
+        result.and(touchpad)
+    }
+
+    /// Types `text` one keystroke per report, so that consumers see every
+    /// modifier before the key it modifies.
+    fn type_text(&self, text: &str) -> Result<(), Error> {
+        let layout = self
+            .layout
+            .ok_or_else(|| Error::Unsupported("Only keyboards type text".to_string()))?;
+        let caps_lock = self.fd.caps_lock.load(Ordering::SeqCst);
+        let mut held = Vec::new();
+        for (key, pressed) in layout
+            .keystrokes(text, caps_lock)
+            .map_err(Error::InvalidInput)?
+        {
+            let input = if pressed {
+                Input::Press(Button::Key(key))
+            } else {
+                Input::Release(Button::Key(key))
+            };
+            if let Err(e) = self.put_input(input) {
+                // Leaves no modifier stuck for whatever is typed next.
+                for key in held.into_iter().rev() {
+                    if let Err(e) = self.put_input(Input::Release(Button::Key(key))) {
+                        log::error!("Failed to release {:?} after typing: {:?}", key, e);
+                    }
                 }
+                return Err(e);
+            }
+            if pressed {
+                held.push(key);
+            } else {
+                held.retain(|other| *other != key);
             }
         }
+        Ok(())
+    }
 
-        let press_input_event = InputEvent::from(SafeInputEvent {
-            r#type: EV_SYN as u16,
-            code: SYN_REPORT as u16,
-            value: 0,
-            time,
-        });
-        unsafe {
-            let n = libc::write(self.fd.0, press_input_event.c_ptr(), InputEvent::size());
-            Errno::result(n).map_with_vgp_error()?;
+    fn set_battery(&self, level: u8, charging: bool) -> Result<(), Error> {
+        if level > 100 {
+            return Err(Error::InvalidInput(InvalidInput::OutOfRange {
+                value: level as f32,
+            }));
         }
+        self.fd
+            .update_hid(|hid| hid.set_battery(level, charging))
+            .unwrap_or_else(|| {
+                Err(Error::Unsupported(
+                    "uinput devices cannot report a battery".to_string(),
+                ))
+            })
+    }
+}
 
-        Ok(())
+/// The input half of a device. It can be cloned and moved to other threads,
+/// and keeps the device plugged in as long as it exists.
+#[derive(Clone)]
+pub struct InputHandle {
+    id: DeviceId,
+    input: Arc<DeviceInput>,
+}
+
+impl InputHandle {
+    pub fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    pub fn set_stick_processing(&self, thumb_stick: ThumbStick, processing: StickProcessing) {
+        let mut config = self.input.config.lock().unwrap();
+        config.stick_processors.set(thumb_stick, processing);
+    }
+
+    pub fn set_value_policy(&self, policy: ValuePolicy) {
+        self.input.config.lock().unwrap().value_policy = policy;
+    }
+
+    pub fn axis_info(&self, axis: Axis) -> Option<AxisInfo> {
+        self.input.axes.get(&axis).copied()
+    }
+
+    pub fn set_turbo(&self, button: Button, turbo: Option<Turbo>) -> Result<(), Error> {
+        self.input.set_turbo(button, turbo)
+    }
+
+    pub fn put_input(&self, input: Input) -> Result<(), Error> {
+        self.input.put_input(input)
+    }
+
+    /// Releases every held button and centers every axis.
+    pub fn reset(&self) -> Result<(), Error> {
+        self.input.reset()
+    }
+
+    /// Types `text` on a keyboard with the layout it was plugged in with.
+    /// Fails before typing anything if the layout cannot type a character.
+    ///
+    /// Caps lock is only honored if the outputs of the device are read, which
+    /// is how the state of its LED is learned. Otherwise letters come out in
+    /// the wrong case once something else turns caps lock on. Keys that are
+    /// still held when putting an input fails are released.
+    pub fn type_text(&self, text: &str) -> Result<(), Error> {
+        self.input.type_text(text)
+    }
+
+    /// Reports a battery charged to `level` percent. Only devices emulated
+    /// through uhid have a battery.
+    pub fn set_battery(&self, level: u8, charging: bool) -> Result<(), Error> {
+        self.input.set_battery(level, charging)
+    }
+
+    /// Resets the device when no input is put on it for `timeout`. `None`
+    /// turns the watchdog off.
+    pub fn set_watchdog(&self, timeout: Option<Duration>) {
+        let mut watchdog = self.input.watchdog.lock().unwrap();
+        match (timeout, &*watchdog) {
+            (Some(timeout), Some(running)) => running.set_timeout(timeout),
+            (Some(timeout), None) => {
+                let input = Arc::downgrade(&self.input);
+                *watchdog = Some(Watchdog::new(
+                    timeout,
+                    Arc::new(move || match input.upgrade() {
+                        Some(input) => input.reset(),
+                        None => Ok(()),
+                    }),
+                ));
+            }
+            (None, _) => *watchdog = None,
+        }
+    }
+
+    fn input_sink(&self) -> InputSink {
+        let input = Arc::downgrade(&self.input);
+        Arc::new(move |i| match input.upgrade() {
+            Some(input) => input.put_input(i),
+            None => Err(Error::Unplugged),
+        })
+    }
+
+    pub fn run_macro(&self, input_macro: &Macro) -> MacroHandle {
+        input_macro::run(input_macro, self.input_sink())
+    }
+
+    /// Creates a scheduler that puts inputs on this device at `frame_rate`
+    /// frames per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_rate` is not a positive number.
+    pub fn scheduler(&self, frame_rate: f64) -> Scheduler {
+        Scheduler::new(frame_rate, self.input_sink())
+    }
+}
+
+/// The output half of a device. Reading outputs and answering force feedback
+/// uploads through it never blocks inputs put through the [`InputHandle`] of
+/// the same device.
+pub struct OutputHandle {
+    id: DeviceId,
+    output: Arc<DeviceOutput>,
+}
+
+impl OutputHandle {
+    pub fn id(&self) -> DeviceId {
+        self.id
     }
 
     pub fn get_output(&mut self) -> Result<Output, Error> {
+        Ok(self.output.read_output()?.unwrap_or(Output::None))
+    }
+}
+
+/// The output side of a device.
+struct DeviceOutput {
+    fd: Arc<DeviceFD>,
+    ff_map: Mutex<HashMap<u32, ForceFeedback>>,
+    /// Outputs that were read along with an earlier one.
+    pending: Mutex<VecDeque<Output>>,
+}
+
+impl DeviceOutput {
+    /// Reads the next event of the device. Returns `None` once there are no
+    /// more events to read.
+    fn read_output(&self) -> Result<Option<Output>, Error> {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(output) = pending.pop_front() {
+            return Ok(Some(output));
+        }
+        if self.fd.hid.is_some() {
+            return Ok(uhid::serve(&self.fd)?.map(|outputs| {
+                pending.extend(outputs);
+                pending.pop_front().unwrap_or(Output::None)
+            }));
+        }
+        drop(pending);
+
+        let mut ff_map = self.ff_map.lock().unwrap();
         let input_event = InputEvent::new();
 
         let n = unsafe {
-            let n = libc::read(self.fd.0, input_event.c_ptr(), InputEvent::size());
+            let n = libc::read(self.fd.raw(), input_event.c_ptr(), InputEvent::size());
             Errno::result(n).map(|n| n as usize)
         };
 
@@ -261,18 +1165,18 @@ impl Device {
                                 ForceFeedbackUpload::new(input_event.value as u32);
 
                             unsafe {
-                                ui_begin_ff_upload(self.fd.0, force_feedback_upload.mut_ptr())
+                                ui_begin_ff_upload(self.fd.raw(), force_feedback_upload.mut_ptr())
                                     .map_with_vgp_error()?;
                             }
 
                             let (effect_id, force_feedback) = force_feedback_upload.get_data();
 
-                            self.ff_map.insert(effect_id, force_feedback);
+                            ff_map.insert(effect_id, force_feedback);
 
                             force_feedback_upload.set_retval(0);
 
                             unsafe {
-                                ui_end_ff_upload(self.fd.0, force_feedback_upload.mut_ptr())
+                                ui_end_ff_upload(self.fd.raw(), force_feedback_upload.mut_ptr())
                                     .map_with_vgp_error()?;
                             }
 
@@ -282,16 +1186,16 @@ impl Device {
                                 ForceFeedbackErase::new(input_event.value as u32);
 
                             unsafe {
-                                ui_begin_ff_erase(self.fd.0, force_feedback_erase.mut_ptr())
+                                ui_begin_ff_erase(self.fd.raw(), force_feedback_erase.mut_ptr())
                                     .map_with_vgp_error()?;
                             }
 
-                            self.ff_map.remove(&force_feedback_erase.get_effect_id());
+                            ff_map.remove(&force_feedback_erase.get_effect_id());
 
                             force_feedback_erase.set_retval(0);
 
                             unsafe {
-                                ui_end_ff_erase(self.fd.0, force_feedback_erase.mut_ptr())
+                                ui_end_ff_erase(self.fd.raw(), force_feedback_erase.mut_ptr())
                                     .map_with_vgp_error()?;
                             }
 
@@ -302,30 +1206,61 @@ impl Device {
                             Ok(Output::Unsupported)
                         }
                     } else if input_event.r#type == EV_FF as u16 {
-                        if input_event.value == 0 {
-                            Ok(Output::Rumble {
-                                large_motor: 0,
-                                small_motor: 0,
-                            })
-                        } else if input_event.value == 1 {
-                            self.ff_map.get(&(input_event.code as u32)).map_or_else(
-                                || Ok(Output::None),
-                                |ff| match ff {
-                                    ForceFeedback::Rumble {
+                        let id = input_event.code as u32;
+                        if input_event.code == FF_GAIN as u16 {
+                            Ok(Output::Gain(input_event.value as u16))
+                        } else if input_event.code == FF_AUTOCENTER as u16 {
+                            Ok(Output::Autocenter(input_event.value as u16))
+                        } else if input_event.value == 0 {
+                            match ff_map.get(&id) {
+                                Some(ForceFeedback::Supported { effect, .. })
+                                    if !matches!(effect, Effect::Rumble { .. }) =>
+                                {
+                                    Ok(Output::EffectStopped { id })
+                                }
+                                _ => Ok(Output::Rumble {
+                                    large_motor: 0,
+                                    small_motor: 0,
+                                }),
+                            }
+                        } else if input_event.value > 0 {
+                            match ff_map.get(&id) {
+                                Some(ForceFeedback::Supported {
+                                    effect,
+                                    direction,
+                                    delay,
+                                    length,
+                                }) => match effect {
+                                    Effect::Rumble {
                                         large_motor,
                                         small_motor,
                                     } => Ok(Output::Rumble {
                                         large_motor: *large_motor,
                                         small_motor: *small_motor,
                                     }),
-                                    ForceFeedback::Unsupported => Ok(Output::None),
+                                    effect => Ok(Output::Effect(PlayedEffect {
+                                        id,
+                                        effect: effect.clone(),
+                                        direction: *direction,
+                                        delay: *delay,
+                                        length: *length,
+                                        count: input_event.value as u32,
+                                    })),
                                 },
-                            )
+                                Some(ForceFeedback::Unsupported) | None => Ok(Output::None),
+                            }
                         } else {
                             Err(Error::Unknown(format!(
-                                "Expected 0 or 1 for value of force feedback input event. Got {}. Input event: {:?}", input_event.value, input_event
+                                "Expected a non-negative value for force feedback input event. Got {}. Input event: {:?}", input_event.value, input_event
                             )))
                         }
+                    } else if input_event.r#type == EV_LED as u16 {
+                        if input_event.code == LED_CAPSL as u16 {
+                            self.fd
+                                .caps_lock
+                                .store(input_event.value != 0, Ordering::SeqCst);
+                        }
+                        Ok(led_output(input_event.code, input_event.value))
                     } else {
                         log::warn!("Got an unsupported input event: {:?}", input_event);
 
@@ -334,23 +1269,159 @@ impl Device {
                 }
             }
             Err(e) => match e {
-                nix::Error::Sys(Errno::EAGAIN) => Ok(Output::None),
+                nix::Error::Sys(Errno::EAGAIN) => return Ok(None),
                 _ => Err(Error::Internal(e)),
             },
         }
+        .map(Some)
+    }
+}
+
+/// What a consumer writing `value` to LED `code` stands for.
+fn led_output(code: u16, value: i32) -> Output {
+    Output::Led {
+        index: code as u8,
+        on: value != 0,
+    }
+}
+
+/// Destroys the device of `fd` and closes it, unless it is still shared. It is
+/// closed when the last owner drops it then.
+fn close_shared(fd: Arc<DeviceFD>) -> Result<(), Error> {
+    fd.destroy()?;
+    match Arc::try_unwrap(fd) {
+        Ok(fd) => fd.close(),
+        Err(_) => Ok(()),
     }
+}
 
+pub struct Device {
+    input: InputHandle,
+    output: OutputHandle,
+}
+
+impl Device {
+    pub fn id(&self) -> DeviceId {
+        self.input.id
+    }
+
+    /// Splits the device into its input and output halves, so that inputs can
+    /// be put on one thread while outputs are read on another. The device is
+    /// unplugged once both halves and every clone of the input half are
+    /// dropped.
+    pub fn split(self) -> (InputHandle, OutputHandle) {
+        (self.input, self.output)
+    }
+
+    pub fn set_stick_processing(&mut self, thumb_stick: ThumbStick, processing: StickProcessing) {
+        self.input.set_stick_processing(thumb_stick, processing)
+    }
+
+    pub fn set_value_policy(&mut self, policy: ValuePolicy) {
+        self.input.set_value_policy(policy)
+    }
+
+    pub fn axis_info(&self, axis: Axis) -> Option<AxisInfo> {
+        self.input.axis_info(axis)
+    }
+
+    pub fn set_turbo(&mut self, button: Button, turbo: Option<Turbo>) -> Result<(), Error> {
+        self.input.set_turbo(button, turbo)
+    }
+
+    pub fn put_input(&mut self, input: Input) -> Result<(), Error> {
+        self.input.put_input(input)
+    }
+
+    /// Releases every held button and centers every axis.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.input.reset()
+    }
+
+    /// Types `text` on a keyboard with the layout it was plugged in with.
+    /// Fails before typing anything if the layout cannot type a character.
+    ///
+    /// Caps lock is only honored if the outputs of the device are read, which
+    /// is how the state of its LED is learned. Otherwise letters come out in
+    /// the wrong case once something else turns caps lock on. Keys that are
+    /// still held when putting an input fails are released.
+    pub fn type_text(&mut self, text: &str) -> Result<(), Error> {
+        self.input.type_text(text)
+    }
+
+    /// Reports a battery charged to `level` percent. Only devices emulated
+    /// through uhid have a battery.
+    pub fn set_battery(&mut self, level: u8, charging: bool) -> Result<(), Error> {
+        self.input.set_battery(level, charging)
+    }
+
+    /// Resets the device when no input is put on it for `timeout`. `None`
+    /// turns the watchdog off.
+    pub fn set_watchdog(&mut self, timeout: Option<Duration>) {
+        self.input.set_watchdog(timeout)
+    }
+
+    pub fn run_macro(&self, input_macro: &Macro) -> MacroHandle {
+        self.input.run_macro(input_macro)
+    }
+
+    /// Creates a scheduler that puts inputs on this device at `frame_rate`
+    /// frames per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_rate` is not a positive number.
+    pub fn scheduler(&self, frame_rate: f64) -> Scheduler {
+        self.input.scheduler(frame_rate)
+    }
+
+    pub fn get_output(&mut self) -> Result<Output, Error> {
+        self.output.get_output()
+    }
+
+    /// Destroys the device and closes its file descriptor.
+    ///
+    /// Input handles that are still around fail with [`Error::Unplugged`]
+    /// from then on. If any of them exist, the file descriptor is closed once
+    /// the last one is dropped.
     pub fn unplug(self) -> Result<(), Error> {
-        Ok(())
+        let fd = Arc::clone(&self.input.input.fd);
+        let companions = self.input.input.companion_fds();
+        self.input.input.watchdog.lock().unwrap().take();
+        self.input.input.turbo.lock().unwrap().take();
+        drop(self);
+
+        companions
+            .into_iter()
+            .fold(close_shared(fd), |result, fd| result.and(close_shared(fd)))
+    }
+
+    /// Releases every held button and centers every axis before unplugging,
+    /// so that consumers that keep the last state of the device are not left
+    /// with stuck inputs.
+    pub fn release_and_unplug(self) -> Result<(), Error> {
+        let reset = self.input.reset();
+        let unplug = self.unplug();
+        reset.and(unplug)
     }
 }
 
-impl Drop for Device {
-    fn drop(&mut self) {
-        unsafe {
-            if let Err(e) = ui_dev_destroy(self.fd.0) {
-                log::error!("Failed to destroy device: {:?}", e);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_led_writes() {
+        assert!(matches!(
+            led_output(LED_CAPSL as u16, 1),
+            Output::Led { index: 1, on: true }
+        ));
+        assert!(matches!(
+            led_output(LED_SCROLLL as u16, 0),
+            Output::Led {
+                index: 2,
+                on: false
             }
-        }
+        ));
     }
 }
@@ -0,0 +1,90 @@
+use std::{ffi::CStr, sync::Arc, time::Instant};
+
+use super::{
+    bindings::consts::*, internal::*, set_up_abs, types::*, DeviceFD, UInputFD, PRODUCT_ID,
+    VENDOR_ID,
+};
+use crate::api::*;
+use crate::config::MotionConfig;
+
+/// The motion sensors of a device, which live on a node of their own.
+pub(super) struct MotionNode {
+    pub fd: Arc<DeviceFD>,
+    accel: AxisInfo,
+    gyro: AxisInfo,
+    start: Instant,
+}
+
+impl MotionNode {
+    pub fn new(config: &MotionConfig, phys: &CStr) -> Result<Self, Error> {
+        let fd = UInputFD::new().map_with_vgp_error()?;
+        let accel = config.accel_info();
+        let gyro = config.gyro_info();
+
+        let setup = Setup::from(SafeSetup {
+            bustype: 0x06,
+            version: 1,
+            vendor: VENDOR_ID,
+            product: PRODUCT_ID,
+            ff_effects_max: 0,
+            name: "virtual motion sensors (vgp)\0",
+        });
+
+        unsafe {
+            ui_set_propbit(fd.0, INPUT_PROP_ACCELEROMETER as u64).map_with_vgp_error()?;
+
+            ui_set_evbit(fd.0, EV_ABS as u64).map_with_vgp_error()?;
+            for code in [ABS_X, ABS_Y, ABS_Z].iter() {
+                set_up_abs(fd.0, *code as u16, &accel)?;
+            }
+            for code in [ABS_RX, ABS_RY, ABS_RZ].iter() {
+                set_up_abs(fd.0, *code as u16, &gyro)?;
+            }
+
+            ui_set_evbit(fd.0, EV_MSC as u64).map_with_vgp_error()?;
+            ui_set_mscbit(fd.0, MSC_TIMESTAMP as u64).map_with_vgp_error()?;
+
+            ui_set_phys(fd.0, phys.as_ptr()).map_with_vgp_error()?;
+            ui_dev_setup(fd.0, setup.const_ptr()).map_with_vgp_error()?;
+            ui_dev_create(fd.0).map_with_vgp_error()?;
+        }
+
+        Ok(Self {
+            fd: Arc::new(DeviceFD::new(fd)),
+            accel,
+            gyro,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn put(&self, accel: [f32; 3], gyro: [f32; 3]) -> Result<(), Error> {
+        let readings = [ABS_X, ABS_Y, ABS_Z]
+            .iter()
+            .zip(accel.iter())
+            .map(|(code, value)| (*code, *value, &self.accel))
+            .chain(
+                [ABS_RX, ABS_RY, ABS_RZ]
+                    .iter()
+                    .zip(gyro.iter())
+                    .map(|(code, value)| (*code, *value, &self.gyro)),
+            );
+
+        let mut events = Vec::with_capacity(7);
+        for (code, value, info) in readings {
+            if value.is_nan() {
+                return Err(Error::InvalidInput(InvalidInput::NotANumber));
+            }
+            let value = (value * info.resolution as f32)
+                .round()
+                .clamp(info.minimum as f32, info.maximum as f32);
+            events.push((EV_ABS as u16, code as u16, value as i32));
+        }
+
+        // Wraps around after a bit more than an hour, like the timestamps of
+        // real controllers do.
+        let timestamp = self.start.elapsed().as_micros() as u32 as i32;
+        events.push((EV_MSC as u16, MSC_TIMESTAMP as u16, timestamp));
+
+        self.fd.write_report(&events)
+    }
+}
@@ -0,0 +1,251 @@
+use super::{
+    bindings::consts::*,
+    uhid::{put_i16, raw_sensor},
+};
+use crate::api::*;
+
+/// Raw gyroscope units per degree per second, and accelerometer units per g,
+/// as the calibration report tells the driver.
+const GYRO_RESOLUTION: f32 = 16.0;
+const ACCEL_RESOLUTION: f32 = 8192.0;
+
+const FINGERS: usize = 2;
+
+/// Where each button is in the button bytes of the input report, by byte and
+/// bit. The D-pad is a hat switch in the low bits of the first byte.
+const BUTTONS: [(u32, usize, u8); 13] = [
+    (BTN_X, 0, 4),
+    (BTN_A, 0, 5),
+    (BTN_B, 0, 6),
+    (BTN_Y, 0, 7),
+    (BTN_TL, 1, 0),
+    (BTN_TR, 1, 1),
+    (BTN_TL2, 1, 2),
+    (BTN_TR2, 1, 3),
+    (BTN_SELECT, 1, 4),
+    (BTN_START, 1, 5),
+    (BTN_THUMBL, 1, 6),
+    (BTN_THUMBR, 1, 7),
+    (BTN_MODE, 2, 0),
+];
+
+const TOUCHPAD_CLICK: (usize, u8) = (2, 1);
+
+#[derive(Default)]
+struct Dpad {
+    up: bool,
+    down: bool,
+    left: bool,
+    right: bool,
+}
+
+impl Dpad {
+    /// The direction of the hat switch, clockwise from north, or 8 when it is
+    /// centered.
+    fn hat(&self) -> u8 {
+        let x = self.right as i8 - self.left as i8;
+        let y = self.down as i8 - self.up as i8;
+        match (x, y) {
+            (0, -1) => 0,
+            (1, -1) => 1,
+            (1, 0) => 2,
+            (1, 1) => 3,
+            (0, 1) => 4,
+            (-1, 1) => 5,
+            (-1, 0) => 6,
+            (-1, -1) => 7,
+            _ => 8,
+        }
+    }
+}
+
+/// The buttons of a DualShock 4 or a DualSense, which both report them in the
+/// same three bytes.
+#[derive(Default)]
+pub(super) struct Buttons {
+    bytes: [u8; 3],
+    dpad: Dpad,
+}
+
+impl Buttons {
+    fn set_bit(&mut self, (byte, bit): (usize, u8), on: bool) {
+        if on {
+            self.bytes[byte] |= 1 << bit;
+        } else {
+            self.bytes[byte] &= !(1 << bit);
+        }
+    }
+
+    pub fn apply(&mut self, code: u32, pressed: bool) {
+        match code {
+            BTN_DPAD_UP => self.dpad.up = pressed,
+            BTN_DPAD_DOWN => self.dpad.down = pressed,
+            BTN_DPAD_LEFT => self.dpad.left = pressed,
+            BTN_DPAD_RIGHT => self.dpad.right = pressed,
+            code => {
+                if let Some((_, byte, bit)) = BUTTONS.iter().find(|(c, _, _)| *c == code) {
+                    self.set_bit((*byte, *bit), pressed);
+                }
+            }
+        }
+    }
+
+    pub fn set_touchpad_click(&mut self, pressed: bool) {
+        self.set_bit(TOUCHPAD_CLICK, pressed);
+    }
+
+    /// The analog value of trigger `index`, 0 for the left one. A pressed
+    /// trigger button reads as fully pressed.
+    pub fn trigger(&self, index: usize, analog: u8) -> u8 {
+        if self.bytes[1] & (1 << (2 + index)) != 0 {
+            u8::MAX
+        } else {
+            analog
+        }
+    }
+
+    pub fn bytes(&self) -> [u8; 3] {
+        let mut bytes = self.bytes;
+        bytes[0] |= self.dpad.hat();
+        bytes
+    }
+}
+
+/// The battery capacity both controllers report, in tenths.
+pub(super) fn battery_capacity(level: u8) -> u8 {
+    (level / 10).min(10)
+}
+
+/// A thumb stick axis as a byte centered on 128.
+pub(super) fn stick(value: i32) -> u8 {
+    (value + 128) as u8
+}
+
+#[derive(Clone, Copy)]
+struct Touch {
+    tracking_id: u8,
+    x: u16,
+    y: u16,
+}
+
+/// A touchpad with two fingers, which both controllers report the same way.
+pub(super) struct Touchpad {
+    width: f32,
+    height: f32,
+    touches: [Option<Touch>; FINGERS],
+    next_tracking_id: u8,
+}
+
+fn touch_position(value: f32, size: f32) -> Result<u16, Error> {
+    if value.is_nan() {
+        return Err(Error::InvalidInput(InvalidInput::NotANumber));
+    }
+    Ok((value.clamp(0.0, 1.0) * (size - 1.0)).round() as u16)
+}
+
+impl Touchpad {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width: width as f32,
+            height: height as f32,
+            touches: [None; FINGERS],
+            next_tracking_id: 0,
+        }
+    }
+
+    fn check_finger(finger: u8) -> Result<(), Error> {
+        if (finger as usize) < FINGERS {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(InvalidInput::UnsupportedFinger(finger)))
+        }
+    }
+
+    pub fn touch(&mut self, finger: u8, x: f32, y: f32) -> Result<(), Error> {
+        Self::check_finger(finger)?;
+        let x = touch_position(x, self.width)?;
+        let y = touch_position(y, self.height)?;
+
+        let tracking_id = match self.touches[finger as usize] {
+            Some(touch) => touch.tracking_id,
+            None => {
+                let tracking_id = self.next_tracking_id;
+                self.next_tracking_id = (tracking_id + 1) & 0x7f;
+                tracking_id
+            }
+        };
+        self.touches[finger as usize] = Some(Touch { tracking_id, x, y });
+        Ok(())
+    }
+
+    pub fn touch_up(&mut self, finger: u8) -> Result<(), Error> {
+        Self::check_finger(finger)?;
+        self.touches[finger as usize] = None;
+        Ok(())
+    }
+
+    pub fn release(&mut self) {
+        self.touches = [None; FINGERS];
+    }
+
+    /// Writes both fingers into `points`, four bytes each.
+    pub fn write_points(&self, points: &mut [u8]) {
+        for (i, touch) in self.touches.iter().enumerate() {
+            let point = &mut points[4 * i..4 * (i + 1)];
+            match touch {
+                Some(touch) => {
+                    point[0] = touch.tracking_id;
+                    point[1] = touch.x as u8;
+                    point[2] = ((touch.x >> 8) as u8 & 0x0f) | ((touch.y as u8 & 0x0f) << 4);
+                    point[3] = (touch.y >> 4) as u8;
+                }
+                // The top bit marks a finger that is not touching.
+                None => point.copy_from_slice(&[0x80, 0, 0, 0]),
+            }
+        }
+    }
+}
+
+/// The gyroscope and then the accelerometer, in the units the calibration
+/// report announces.
+pub(super) fn motion(accel: [f32; 3], gyro: [f32; 3]) -> Result<[u8; 12], Error> {
+    let mut bytes = [0; 12];
+    for (i, value) in gyro.iter().enumerate() {
+        put_i16(&mut bytes, 2 * i, raw_sensor(*value, GYRO_RESOLUTION)?);
+    }
+    for (i, value) in accel.iter().enumerate() {
+        put_i16(&mut bytes, 6 + 2 * i, raw_sensor(*value, ACCEL_RESOLUTION)?);
+    }
+    Ok(bytes)
+}
+
+/// The calibration feature report, with no bias and a symmetric range on every
+/// axis, so that the driver's calibration leaves the values as they are sent.
+pub(super) fn calibration_report(number: u8, size: usize) -> Vec<u8> {
+    let mut report = vec![0; size];
+    report[0] = number;
+
+    let gyro_range = 540 * GYRO_RESOLUTION as i16;
+    for axis in 0..3 {
+        put_i16(&mut report, 7 + 4 * axis, gyro_range);
+        put_i16(&mut report, 9 + 4 * axis, -gyro_range);
+    }
+    put_i16(&mut report, 19, 540);
+    put_i16(&mut report, 21, 540);
+    for axis in 0..3 {
+        put_i16(&mut report, 23 + 4 * axis, ACCEL_RESOLUTION as i16);
+        put_i16(&mut report, 25 + 4 * axis, -ACCEL_RESOLUTION as i16);
+    }
+    report
+}
+
+/// A feature report that carries the MAC address, which the drivers read from
+/// right after the report id, least significant byte first.
+pub(super) fn mac_report(number: u8, size: usize, mac: [u8; 6]) -> Vec<u8> {
+    let mut report = vec![0; size];
+    report[0] = number;
+    for (i, byte) in mac.iter().rev().enumerate() {
+        report[1 + i] = *byte;
+    }
+    report
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    os::unix::io::RawFd,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use nix::{
+    errno::Errno,
+    sys::epoll::{
+        epoll_create1, epoll_ctl, epoll_wait, EpollCreateFlags, EpollEvent, EpollFlags, EpollOp,
+    },
+};
+
+use super::{Device, DeviceFD, DeviceOutput, OutputHandle};
+use crate::api::*;
+
+const MAX_EVENTS: usize = 32;
+
+/// An output of one of the polled devices, or the error reading it failed
+/// with.
+pub type Polled = (DeviceId, Result<Output, Error>);
+
+struct Registration {
+    id: DeviceId,
+    fd: RawFd,
+    /// Keeps the device's file descriptor apart from another one that gets the
+    /// same number once it is closed.
+    device_fd: Weak<DeviceFD>,
+    output: Weak<DeviceOutput>,
+}
+
+/// Waits for outputs of many devices at once on a single epoll instance.
+///
+/// The poller does not keep its devices plugged in. Devices that get dropped
+/// are forgotten on their own.
+pub struct Poller {
+    epoll: RawFd,
+    registrations: HashMap<u64, Registration>,
+    next_token: u64,
+}
+
+impl Poller {
+    pub fn new() -> Result<Self, Error> {
+        let epoll = epoll_create1(EpollCreateFlags::EPOLL_CLOEXEC).map_with_vgp_error()?;
+
+        Ok(Self {
+            epoll,
+            registrations: HashMap::new(),
+            next_token: 0,
+        })
+    }
+
+    pub fn register(&mut self, device: &Device) -> Result<(), Error> {
+        self.register_output(&device.output)
+    }
+
+    pub fn deregister(&mut self, device: &Device) -> Result<(), Error> {
+        self.deregister_output(&device.output)
+    }
+
+    /// Registers the output half of a split device.
+    pub fn register_output(&mut self, output: &OutputHandle) -> Result<(), Error> {
+        let fd = output.output.fd.raw();
+        let token = self.next_token;
+
+        let mut event = EpollEvent::new(EpollFlags::EPOLLIN, token);
+        epoll_ctl(self.epoll, EpollOp::EpollCtlAdd, fd, &mut event).map_with_vgp_error()?;
+
+        self.next_token += 1;
+        self.registrations.insert(
+            token,
+            Registration {
+                id: output.id(),
+                fd,
+                device_fd: Arc::downgrade(&output.output.fd),
+                output: Arc::downgrade(&output.output),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn deregister_output(&mut self, output: &OutputHandle) -> Result<(), Error> {
+        let output = Arc::downgrade(&output.output);
+        let token = self
+            .registrations
+            .iter()
+            .find(|(_, registration)| registration.output.ptr_eq(&output))
+            .map(|(token, _)| *token);
+
+        if let Some(token) = token {
+            let registration = self.registrations.remove(&token).unwrap();
+            epoll_ctl(
+                self.epoll,
+                EpollOp::EpollCtlDel,
+                registration.fd,
+                None::<&mut EpollEvent>,
+            )
+            .map_with_vgp_error()?;
+        }
+        Ok(())
+    }
+
+    /// Waits until at least one of the devices has an output, or until
+    /// `timeout` has passed. `None` waits indefinitely.
+    ///
+    /// Returns every output that was pending on the devices that woke the
+    /// poller up. Force feedback uploads and erasures are handled on the way
+    /// and are not returned. A device that fails to be read has its error
+    /// returned after the outputs read from it before, and the other devices
+    /// are read all the same.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<Vec<Polled>, Error> {
+        self.prune()?;
+
+        let timeout_ms = timeout.map_or(-1, |t| t.as_millis().min(isize::MAX as u128) as isize);
+
+        let mut events = [EpollEvent::empty(); MAX_EVENTS];
+        let n = match epoll_wait(self.epoll, &mut events, timeout_ms) {
+            Ok(n) => n,
+            Err(nix::Error::Sys(Errno::EINTR)) => 0,
+            Err(e) => return Err(Error::Internal(e)),
+        };
+
+        let mut outputs = Vec::new();
+        for event in events[..n].iter() {
+            let token = event.data();
+            // A device that is gone took its file descriptor out of the epoll
+            // instance when it was closed.
+            let (id, output) = match self.registrations.get(&token) {
+                Some(registration) => match registration.output.upgrade() {
+                    Some(output) => (registration.id, output),
+                    None => continue,
+                },
+                None => continue,
+            };
+
+            loop {
+                match output.read_output() {
+                    Ok(Some(Output::None)) => {}
+                    Ok(Some(o)) => outputs.push((id, Ok(o))),
+                    Ok(None) => break,
+                    Err(e) => {
+                        outputs.push((id, Err(e)));
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    /// Forgets the devices whose output half was dropped. Their file
+    /// descriptor can still be open for the input half, in which case it has
+    /// to leave the epoll instance or it would wake the poller up forever.
+    fn prune(&mut self) -> Result<(), Error> {
+        let epoll = self.epoll;
+        let mut result = Ok(());
+        self.registrations.retain(|_, registration| {
+            if registration.output.strong_count() > 0 {
+                return true;
+            }
+            if let Some(fd) = registration.device_fd.upgrade() {
+                if let Err(e) = epoll_ctl(
+                    epoll,
+                    EpollOp::EpollCtlDel,
+                    fd.raw(),
+                    None::<&mut EpollEvent>,
+                )
+                .map_with_vgp_error()
+                {
+                    result = Err(e);
+                }
+            }
+            false
+        });
+        result
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        if let Err(e) = nix::unistd::close(self.epoll) {
+            log::error!("Failed to close the poller's file descriptor: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::time::Duration;
+
+use super::{
+    bindings::consts::*,
+    uhid::{put_i16, raw_sensor, HidProfile},
+};
+use crate::api::*;
+use crate::force_feedback::HdRumble;
+
+const VENDOR_ID: u32 = 0x057e;
+const PRODUCT_ID: u32 = 0x2009;
+
+const REPORT_SIZE: usize = 64;
+
+// Input reports.
+const SUBCOMMAND_REPLY: u8 = 0x21;
+const FULL_REPORT: u8 = 0x30;
+const USB_REPLY: u8 = 0x81;
+
+// Output reports.
+const RUMBLE_AND_SUBCOMMAND: u8 = 0x01;
+const RUMBLE: u8 = 0x10;
+const USB_COMMAND: u8 = 0x80;
+
+const USB_CONNECTION_STATUS: u8 = 0x01;
+
+const SUBCOMMAND_DEVICE_INFO: u8 = 0x02;
+const SUBCOMMAND_SPI_FLASH_READ: u8 = 0x10;
+const SUBCOMMAND_PLAYER_LIGHTS: u8 = 0x30;
+
+/// The most a single SPI flash read returns.
+const SPI_FLASH_READ_MAX: usize = 0x1d;
+
+/// The battery is powered over USB, which is the lowest bit of its byte.
+const USB_POWERED: u8 = 0x01;
+
+/// Raw accelerometer units per g and gyroscope units per degree per second,
+/// for the factory calibration below.
+const ACCEL_RESOLUTION: f32 = 4096.0;
+const GYRO_RESOLUTION: f32 = 13371.0 / 936.0;
+
+/// Sticks report 12 bits, centered on this.
+const STICK_CENTER: i32 = 0x800;
+
+const NEUTRAL_RUMBLE: [u8; 8] = [0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40];
+
+/// Where each button is in the button bytes of the input report, by byte and
+/// bit. The bytes hold the buttons of the right side, the shared ones and
+/// the left side. Buttons go by their position, so `BTN_SOUTH` is B.
+const BUTTONS: [(u32, usize, u8); 18] = [
+    (BTN_X, 0, 0),
+    (BTN_Y, 0, 1),
+    (BTN_A, 0, 2),
+    (BTN_B, 0, 3),
+    (BTN_TR, 0, 6),
+    (BTN_TR2, 0, 7),
+    (BTN_SELECT, 1, 0),
+    (BTN_START, 1, 1),
+    (BTN_THUMBR, 1, 2),
+    (BTN_THUMBL, 1, 3),
+    (BTN_MODE, 1, 4),
+    (KEY_RECORD, 1, 5),
+    (BTN_DPAD_DOWN, 2, 0),
+    (BTN_DPAD_UP, 2, 1),
+    (BTN_DPAD_RIGHT, 2, 2),
+    (BTN_DPAD_LEFT, 2, 3),
+    (BTN_TL, 2, 6),
+    (BTN_TL2, 2, 7),
+];
+
+/// The factory calibration of the motion sensors: no offsets, and the
+/// scales the resolutions above follow from.
+#[rustfmt::skip]
+const IMU_CALIBRATION: [u8; 24] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x40, 0x00, 0x40,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3b, 0x34, 0x3b, 0x34, 0x3b, 0x34,
+];
+
+/// The factory calibration of the sticks, with their full range around the
+/// center. The left stick lists the range above the center, the center and
+/// the range below it, the right stick starts with the center.
+const LEFT_STICK_CALIBRATION: [u8; 9] = [0xff, 0xf7, 0x7f, 0x00, 0x08, 0x80, 0xff, 0xf7, 0x7f];
+const RIGHT_STICK_CALIBRATION: [u8; 9] = [0x00, 0x08, 0x80, 0xff, 0xf7, 0x7f, 0xff, 0xf7, 0x7f];
+
+/// Body and button colors.
+const COLORS: [u8; 6] = [0x32, 0x32, 0x32, 0xff, 0xff, 0xff];
+
+/// The parts of the SPI flash that drivers read, by address. Everything else
+/// reads as erased, including the user calibration.
+const SPI_FLASH: [(u32, &[u8]); 4] = [
+    (0x6020, &IMU_CALIBRATION),
+    (0x603d, &LEFT_STICK_CALIBRATION),
+    (0x6046, &RIGHT_STICK_CALIBRATION),
+    (0x6050, &COLORS),
+];
+
+/// The report descriptor of a Pro Controller on USB.
+#[rustfmt::skip]
+const DESCRIPTOR: [u8; 203] = [
+    0x05, 0x01, 0x15, 0x00, 0x09, 0x04, 0xa1, 0x01, 0x85, 0x30, 0x05, 0x01, 0x05, 0x09, 0x19, 0x01,
+    0x29, 0x0a, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x0a, 0x55, 0x00, 0x65, 0x00, 0x81, 0x02,
+    0x05, 0x09, 0x19, 0x0b, 0x29, 0x0e, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x04, 0x81, 0x02,
+    0x75, 0x01, 0x95, 0x02, 0x81, 0x03, 0x0b, 0x01, 0x00, 0x01, 0x00, 0xa1, 0x00, 0x0b, 0x30, 0x00,
+    0x01, 0x00, 0x0b, 0x31, 0x00, 0x01, 0x00, 0x0b, 0x32, 0x00, 0x01, 0x00, 0x0b, 0x35, 0x00, 0x01,
+    0x00, 0x15, 0x00, 0x27, 0xff, 0xff, 0x00, 0x00, 0x75, 0x10, 0x95, 0x04, 0x81, 0x02, 0xc0, 0x0b,
+    0x39, 0x00, 0x01, 0x00, 0x15, 0x00, 0x25, 0x07, 0x35, 0x00, 0x46, 0x3b, 0x01, 0x65, 0x14, 0x75,
+    0x04, 0x95, 0x01, 0x81, 0x02, 0x05, 0x09, 0x19, 0x0f, 0x29, 0x12, 0x15, 0x00, 0x25, 0x01, 0x75,
+    0x01, 0x95, 0x04, 0x81, 0x02, 0x75, 0x08, 0x95, 0x34, 0x81, 0x03, 0x06, 0x00, 0xff, 0x85, 0x21,
+    0x09, 0x01, 0x75, 0x08, 0x95, 0x3f, 0x81, 0x03, 0x85, 0x81, 0x09, 0x02, 0x75, 0x08, 0x95, 0x3f,
+    0x81, 0x03, 0x85, 0x01, 0x09, 0x03, 0x75, 0x08, 0x95, 0x3f, 0x91, 0x83, 0x85, 0x10, 0x09, 0x04,
+    0x75, 0x08, 0x95, 0x3f, 0x91, 0x83, 0x85, 0x80, 0x09, 0x05, 0x75, 0x08, 0x95, 0x3f, 0x91, 0x83,
+    0x85, 0x82, 0x09, 0x06, 0x75, 0x08, 0x95, 0x3f, 0x91, 0x83, 0xc0,
+];
+
+/// A Pro Controller on USB, as `hid-nintendo` drives it. The driver talks to
+/// it with subcommands in output reports, which are answered in input
+/// reports of their own, and expects a full report every few milliseconds.
+pub(super) struct SwitchPro {
+    mac: [u8; 6],
+    buttons: [u8; 3],
+    /// Both sticks as X and Y, in the 12 bits they are reported in.
+    sticks: [u16; 4],
+    /// The accelerometer and then the gyroscope.
+    imu: [u8; 12],
+    timer: u8,
+    /// The battery level from 0 to 4, whether it charges and how it is
+    /// powered.
+    battery: u8,
+    rumble: [u8; 8],
+    replies: Vec<Vec<u8>>,
+}
+
+impl SwitchPro {
+    pub fn new(mac: [u8; 6]) -> Self {
+        Self {
+            mac,
+            buttons: [0; 3],
+            sticks: [STICK_CENTER as u16; 4],
+            imu: [0; 12],
+            timer: 0,
+            // Full and charging.
+            battery: 4 << 5 | 0x10 | USB_POWERED,
+            rumble: NEUTRAL_RUMBLE,
+            replies: Vec::new(),
+        }
+    }
+
+    fn set_button(&mut self, code: u32, pressed: bool) {
+        if let Some((_, byte, bit)) = BUTTONS.iter().find(|(c, _, _)| *c == code) {
+            if pressed {
+                self.buttons[*byte] |= 1 << bit;
+            } else {
+                self.buttons[*byte] &= !(1 << bit);
+            }
+        }
+    }
+
+    /// A report of `id` that starts with the current state, as every input
+    /// report does.
+    fn report(&mut self, id: u8) -> [u8; REPORT_SIZE] {
+        let mut report = [0; REPORT_SIZE];
+        report[0] = id;
+        report[1] = self.timer;
+        report[2] = self.battery;
+        report[3..6].copy_from_slice(&self.buttons);
+        for (i, stick) in self.sticks.chunks(2).enumerate() {
+            let (x, y) = (stick[0], stick[1]);
+            let offset = 6 + 3 * i;
+            report[offset] = x as u8;
+            report[offset + 1] = (x >> 8) as u8 | ((y as u8 & 0x0f) << 4);
+            report[offset + 2] = (y >> 4) as u8;
+        }
+        self.timer = self.timer.wrapping_add(1);
+        report
+    }
+
+    /// Reads `size` bytes from `address` on. Reads past the end of the
+    /// address space come back erased, like the rest of the flash.
+    fn spi_flash(address: u32, size: usize) -> Vec<u8> {
+        (0..size as u32)
+            .map(|offset| {
+                address
+                    .checked_add(offset)
+                    .and_then(|address| {
+                        SPI_FLASH.iter().find_map(|(start, bytes)| {
+                            address
+                                .checked_sub(*start)
+                                .and_then(|offset| bytes.get(offset as usize))
+                        })
+                    })
+                    .copied()
+                    .unwrap_or(0xff)
+            })
+            .collect()
+    }
+
+    fn usb_command(&mut self, command: u8) {
+        let mut reply = vec![0; REPORT_SIZE];
+        reply[0] = USB_REPLY;
+        reply[1] = command;
+        if command == USB_CONNECTION_STATUS {
+            // The controller type, then the MAC address backwards.
+            reply[3] = 0x03;
+            for (i, byte) in self.mac.iter().rev().enumerate() {
+                reply[4 + i] = *byte;
+            }
+        }
+        self.replies.push(reply);
+    }
+
+    /// Answers subcommand `id`, and returns the output it stands for, if any.
+    fn subcommand(&mut self, id: u8, arguments: &[u8]) -> Option<Output> {
+        let mut output = None;
+        let (ack, data) = match id {
+            SUBCOMMAND_DEVICE_INFO => {
+                // The firmware version, the controller type and the MAC
+                // address, then that the colors are in the SPI flash.
+                let mut data = vec![0x03, 0x48, 0x03, 0x02];
+                data.extend_from_slice(&self.mac);
+                data.extend_from_slice(&[0x01, 0x01]);
+                (0x82, data)
+            }
+            SUBCOMMAND_SPI_FLASH_READ if arguments.len() >= 5 => {
+                let address =
+                    u32::from_le_bytes([arguments[0], arguments[1], arguments[2], arguments[3]]);
+                let size = (arguments[4] as usize).min(SPI_FLASH_READ_MAX);
+                let mut data = arguments[..4].to_vec();
+                data.push(size as u8);
+                data.extend(Self::spi_flash(address, size));
+                (0x90, data)
+            }
+            SUBCOMMAND_PLAYER_LIGHTS if !arguments.is_empty() => {
+                // The high nibble makes lights flash, which is left out.
+                output = Some(Output::PlayerLeds(arguments[0] & 0x0f));
+                (0x80, Vec::new())
+            }
+            _ => (0x80, Vec::new()),
+        };
+
+        let mut reply = self.report(SUBCOMMAND_REPLY);
+        reply[13] = ack;
+        reply[14] = id;
+        reply[15..15 + data.len()].copy_from_slice(&data);
+        self.replies.push(reply.to_vec());
+        output
+    }
+
+    /// The rumble of both sides, if it changed. Every subcommand carries it
+    /// along, mostly unchanged.
+    fn rumble(&mut self, data: &[u8]) -> Option<Output> {
+        if data == self.rumble {
+            return None;
+        }
+        self.rumble.copy_from_slice(data);
+
+        let mut left = [0; 4];
+        let mut right = [0; 4];
+        left.copy_from_slice(&data[..4]);
+        right.copy_from_slice(&data[4..]);
+        Some(Output::HdRumble {
+            left: HdRumble { raw: left },
+            right: HdRumble { raw: right },
+        })
+    }
+}
+
+fn raw_stick(value: i32) -> u16 {
+    (STICK_CENTER + value).clamp(0, 0xfff) as u16
+}
+
+impl HidProfile for SwitchPro {
+    fn name(&self) -> &str {
+        "Nintendo Co., Ltd. Pro Controller"
+    }
+
+    fn ids(&self) -> (u32, u32, u32) {
+        (VENDOR_ID, PRODUCT_ID, 0x0210)
+    }
+
+    fn descriptor(&self) -> &[u8] {
+        &DESCRIPTOR
+    }
+
+    fn apply(&mut self, r#type: u16, code: u16, value: i32) {
+        // Up is positive on the sticks.
+        match (r#type as u32, code as u32) {
+            (EV_KEY, code) => self.set_button(code, value != 0),
+            (EV_ABS, ABS_X) => self.sticks[0] = raw_stick(value),
+            (EV_ABS, ABS_Y) => self.sticks[1] = raw_stick(-value),
+            (EV_ABS, ABS_RX) => self.sticks[2] = raw_stick(value),
+            (EV_ABS, ABS_RY) => self.sticks[3] = raw_stick(-value),
+            _ => {}
+        }
+    }
+
+    fn motion(&mut self, accel: [f32; 3], gyro: [f32; 3]) -> Result<(), Error> {
+        let mut imu = [0; 12];
+        for (i, value) in accel.iter().enumerate() {
+            let raw = raw_sensor(*value, ACCEL_RESOLUTION)?;
+            put_i16(&mut imu, 2 * i, raw);
+        }
+        for (i, value) in gyro.iter().enumerate() {
+            let raw = raw_sensor(*value, GYRO_RESOLUTION)?;
+            put_i16(&mut imu, 6 + 2 * i, raw);
+        }
+        self.imu = imu;
+        Ok(())
+    }
+
+    fn set_battery(&mut self, level: u8, charging: bool) -> Result<(), Error> {
+        let level = ((level + 12) / 25).min(4);
+        self.battery = level << 5 | (charging as u8) << 4 | USB_POWERED;
+        Ok(())
+    }
+
+    fn input_report(&mut self) -> Vec<u8> {
+        let mut report = self.report(FULL_REPORT);
+        // Three samples of the motion sensors, which are all the same.
+        for sample in 0..3 {
+            let offset = 13 + 12 * sample;
+            report[offset..offset + 12].copy_from_slice(&self.imu);
+        }
+        report.to_vec()
+    }
+
+    fn output(&mut self, report: &[u8]) -> Vec<Output> {
+        match report.first() {
+            Some(&USB_COMMAND) if report.len() >= 2 => {
+                self.usb_command(report[1]);
+                Vec::new()
+            }
+            Some(&RUMBLE_AND_SUBCOMMAND) if report.len() >= 11 => {
+                let rumble = self.rumble(&report[2..10]);
+                let subcommand = self.subcommand(report[10], &report[11..]);
+                rumble.into_iter().chain(subcommand).collect()
+            }
+            Some(&RUMBLE) if report.len() >= 10 => {
+                self.rumble(&report[2..10]).into_iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn take_replies(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.replies)
+    }
+
+    fn report_interval(&self) -> Option<Duration> {
+        Some(Duration::from_millis(8))
+    }
+
+    fn feature_report(&self, _number: u8) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+
+    /// An output report with the neutral rumble and subcommand `id`.
+    fn subcommand(id: u8, arguments: &[u8]) -> Vec<u8> {
+        let mut report = vec![RUMBLE_AND_SUBCOMMAND, 0];
+        report.extend_from_slice(&NEUTRAL_RUMBLE);
+        report.push(id);
+        report.extend_from_slice(arguments);
+        report
+    }
+
+    #[test]
+    fn packs_buttons_and_centers_the_sticks() {
+        let mut pro = SwitchPro::new(MAC);
+        let report = pro.input_report();
+        assert_eq!(report[0], FULL_REPORT);
+        assert_eq!(report[3..6], [0; 3]);
+        assert_eq!(report[6..12], [0x00, 0x08, 0x80, 0x00, 0x08, 0x80]);
+
+        pro.apply(EV_KEY as u16, BTN_A as u16, 1);
+        pro.apply(EV_KEY as u16, KEY_RECORD as u16, 1);
+        pro.apply(EV_KEY as u16, BTN_DPAD_LEFT as u16, 1);
+        pro.apply(EV_ABS as u16, ABS_Y as u16, -0x7ff);
+        pro.apply(EV_ABS as u16, ABS_RX as u16, -0x800);
+        let report = pro.input_report();
+        assert_eq!(report[1], 1);
+        assert_eq!(report[3..6], [0x04, 0x20, 0x08]);
+        // Up is positive, so the left stick is pushed all the way up.
+        assert_eq!(report[6..12], [0x00, 0xf8, 0xff, 0x00, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn answers_usb_commands() {
+        let mut pro = SwitchPro::new(MAC);
+        assert!(pro.output(&[USB_COMMAND, USB_CONNECTION_STATUS]).is_empty());
+
+        let replies = pro.take_replies();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(
+            replies[0][..10],
+            [
+                USB_REPLY,
+                USB_CONNECTION_STATUS,
+                0,
+                0x03,
+                0xbc,
+                0x9a,
+                0x78,
+                0x56,
+                0x34,
+                0x12
+            ]
+        );
+        assert!(pro.take_replies().is_empty());
+    }
+
+    #[test]
+    fn reads_the_spi_flash() {
+        let mut pro = SwitchPro::new(MAC);
+        let report = subcommand(SUBCOMMAND_SPI_FLASH_READ, &[0x3d, 0x60, 0x00, 0x00, 9]);
+        assert!(pro.output(&report).is_empty());
+
+        let reply = pro.take_replies().remove(0);
+        assert_eq!(reply[0], SUBCOMMAND_REPLY);
+        assert_eq!(reply[13..15], [0x90, SUBCOMMAND_SPI_FLASH_READ]);
+        assert_eq!(reply[15..20], [0x3d, 0x60, 0x00, 0x00, 9]);
+        assert_eq!(reply[20..29], LEFT_STICK_CALIBRATION);
+
+        // The user calibration is erased, and so is everything past the end.
+        assert_eq!(SwitchPro::spi_flash(0x8010, 4), [0xff; 4]);
+        assert_eq!(
+            SwitchPro::spi_flash(u32::MAX - 1, SPI_FLASH_READ_MAX),
+            [0xff; SPI_FLASH_READ_MAX]
+        );
+    }
+
+    #[test]
+    fn sets_player_lights_and_rumble() {
+        let mut pro = SwitchPro::new(MAC);
+        let outputs = pro.output(&subcommand(SUBCOMMAND_PLAYER_LIGHTS, &[0x15]));
+        assert_eq!(outputs.len(), 1);
+        assert!(matches!(outputs[0], Output::PlayerLeds(0x05)));
+        let reply = pro.take_replies().remove(0);
+        assert_eq!(reply[13..15], [0x80, SUBCOMMAND_PLAYER_LIGHTS]);
+
+        let mut report = vec![RUMBLE, 1];
+        report.extend_from_slice(&[0x28, 0x88, 0x60, 0x61, 0x28, 0x88, 0x60, 0x61]);
+        let outputs = pro.output(&report);
+        match outputs.as_slice() {
+            [Output::HdRumble { left, right }] => {
+                assert_eq!(left.raw, [0x28, 0x88, 0x60, 0x61]);
+                assert_eq!(right.raw, [0x28, 0x88, 0x60, 0x61]);
+            }
+            outputs => panic!("Expected an HD rumble, got {:?}", outputs),
+        }
+        // Rumble that did not change is not reported again.
+        assert!(pro.output(&report).is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    ffi::CStr,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    bindings::consts::*, internal::*, set_up_abs, types::*, DeviceFD, UInputFD, PRODUCT_ID,
+    VENDOR_ID,
+};
+use crate::api::*;
+use crate::config::TouchpadConfig;
+
+const MAX_TRACKING_ID: i32 = 0xffff;
+
+/// The tool buttons that tell how many fingers are on the touchpad, from one
+/// finger up.
+const TOOLS: [u32; 5] = [
+    BTN_TOOL_FINGER,
+    BTN_TOOL_DOUBLETAP,
+    BTN_TOOL_TRIPLETAP,
+    BTN_TOOL_QUADTAP,
+    BTN_TOOL_QUINTTAP,
+];
+
+#[derive(Clone, Copy)]
+struct Contact {
+    x: i32,
+    y: i32,
+}
+
+struct TouchState {
+    /// The finger in each slot, if it is down.
+    contacts: Vec<Option<Contact>>,
+    next_tracking_id: i32,
+}
+
+/// The touchpad of a device, which lives on a node of its own. Fingers are
+/// reported in multitouch slots, and as a single touch for consumers that do
+/// not know about slots.
+pub(super) struct TouchpadNode {
+    pub fd: Arc<DeviceFD>,
+    x: AxisInfo,
+    y: AxisInfo,
+    state: Mutex<TouchState>,
+}
+
+impl TouchpadNode {
+    pub fn new(config: &TouchpadConfig, phys: &CStr) -> Result<Self, Error> {
+        let fd = UInputFD::new().map_with_vgp_error()?;
+        let x = AxisInfo {
+            minimum: 0,
+            maximum: config.width - 1,
+            fuzz: 0,
+            flat: 0,
+            resolution: config.resolution,
+        };
+        let y = AxisInfo {
+            maximum: config.height - 1,
+            ..x
+        };
+        let slot = AxisInfo {
+            minimum: 0,
+            maximum: config.fingers as i32 - 1,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        };
+        let tracking_id = AxisInfo {
+            maximum: MAX_TRACKING_ID,
+            ..slot
+        };
+
+        let setup = Setup::from(SafeSetup {
+            bustype: 0x06,
+            version: 1,
+            vendor: VENDOR_ID,
+            product: PRODUCT_ID,
+            ff_effects_max: 0,
+            name: "virtual touchpad (vgp)\0",
+        });
+
+        unsafe {
+            ui_set_propbit(fd.0, INPUT_PROP_POINTER as u64).map_with_vgp_error()?;
+            ui_set_propbit(fd.0, INPUT_PROP_BUTTONPAD as u64).map_with_vgp_error()?;
+
+            ui_set_evbit(fd.0, EV_KEY as u64).map_with_vgp_error()?;
+            ui_set_keybit(fd.0, BTN_LEFT as u64).map_with_vgp_error()?;
+            ui_set_keybit(fd.0, BTN_TOUCH as u64).map_with_vgp_error()?;
+            for tool in TOOLS[..config.fingers as usize].iter() {
+                ui_set_keybit(fd.0, *tool as u64).map_with_vgp_error()?;
+            }
+
+            ui_set_evbit(fd.0, EV_ABS as u64).map_with_vgp_error()?;
+            set_up_abs(fd.0, ABS_X as u16, &x)?;
+            set_up_abs(fd.0, ABS_Y as u16, &y)?;
+            set_up_abs(fd.0, ABS_MT_SLOT as u16, &slot)?;
+            set_up_abs(fd.0, ABS_MT_TRACKING_ID as u16, &tracking_id)?;
+            set_up_abs(fd.0, ABS_MT_POSITION_X as u16, &x)?;
+            set_up_abs(fd.0, ABS_MT_POSITION_Y as u16, &y)?;
+
+            ui_set_phys(fd.0, phys.as_ptr()).map_with_vgp_error()?;
+            ui_dev_setup(fd.0, setup.const_ptr()).map_with_vgp_error()?;
+            ui_dev_create(fd.0).map_with_vgp_error()?;
+        }
+
+        Ok(Self {
+            fd: Arc::new(DeviceFD::new(fd)),
+            x,
+            y,
+            state: Mutex::new(TouchState {
+                contacts: vec![None; config.fingers as usize],
+                next_tracking_id: 0,
+            }),
+        })
+    }
+
+    pub(super) fn position(value: f32, info: &AxisInfo) -> Result<i32, Error> {
+        if value.is_nan() {
+            return Err(Error::InvalidInput(InvalidInput::NotANumber));
+        }
+        Ok((value.clamp(0.0, 1.0) * info.maximum as f32).round() as i32)
+    }
+
+    fn check_finger(state: &TouchState, finger: u8) -> Result<(), Error> {
+        if (finger as usize) < state.contacts.len() {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(InvalidInput::UnsupportedFinger(finger)))
+        }
+    }
+
+    pub fn touch(&self, finger: u8, x: f32, y: f32) -> Result<(), Error> {
+        let contact = Contact {
+            x: Self::position(x, &self.x)?,
+            y: Self::position(y, &self.y)?,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        Self::check_finger(&state, finger)?;
+
+        let mut events = vec![(EV_ABS as u16, ABS_MT_SLOT as u16, finger as i32)];
+        if state.contacts[finger as usize].is_none() {
+            let tracking_id = state.next_tracking_id;
+            state.next_tracking_id = (tracking_id + 1) % (MAX_TRACKING_ID + 1);
+            events.push((EV_ABS as u16, ABS_MT_TRACKING_ID as u16, tracking_id));
+        }
+        events.push((EV_ABS as u16, ABS_MT_POSITION_X as u16, contact.x));
+        events.push((EV_ABS as u16, ABS_MT_POSITION_Y as u16, contact.y));
+        state.contacts[finger as usize] = Some(contact);
+
+        self.write_report(&state, events)
+    }
+
+    pub fn touch_up(&self, finger: u8) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        Self::check_finger(&state, finger)?;
+
+        if state.contacts[finger as usize].take().is_none() {
+            return Ok(());
+        }
+        let events = vec![
+            (EV_ABS as u16, ABS_MT_SLOT as u16, finger as i32),
+            (EV_ABS as u16, ABS_MT_TRACKING_ID as u16, -1),
+        ];
+
+        self.write_report(&state, events)
+    }
+
+    pub fn click(&self, pressed: bool) -> Result<(), Error> {
+        self.fd
+            .write_report(&[(EV_KEY as u16, BTN_LEFT as u16, pressed as i32)])
+    }
+
+    /// Lifts every finger and releases the click in a single report.
+    pub fn reset(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let mut events = vec![(EV_KEY as u16, BTN_LEFT as u16, 0)];
+        for (slot, contact) in state.contacts.iter_mut().enumerate() {
+            if contact.take().is_some() {
+                events.push((EV_ABS as u16, ABS_MT_SLOT as u16, slot as i32));
+                events.push((EV_ABS as u16, ABS_MT_TRACKING_ID as u16, -1));
+            }
+        }
+
+        self.write_report(&state, events)
+    }
+
+    /// Writes `events` along with the single touch state that follows from
+    /// the fingers in `state`.
+    fn write_report(
+        &self,
+        state: &TouchState,
+        mut events: Vec<(u16, u16, i32)>,
+    ) -> Result<(), Error> {
+        let fingers = state.contacts.iter().flatten().count();
+        events.push((EV_KEY as u16, BTN_TOUCH as u16, (fingers > 0) as i32));
+        for (i, tool) in TOOLS[..state.contacts.len()].iter().enumerate() {
+            events.push((EV_KEY as u16, *tool as u16, (fingers == i + 1) as i32));
+        }
+        if let Some(first) = state.contacts.iter().flatten().next() {
+            events.push((EV_ABS as u16, ABS_X as u16, first.x));
+            events.push((EV_ABS as u16, ABS_Y as u16, first.y));
+        }
+
+        self.fd.write_report(&events)
+    }
+}
@@ -1,7 +1,11 @@
-use std::alloc::{alloc, dealloc, Layout};
+use std::{
+    alloc::{alloc, dealloc, Layout},
+    time::Duration,
+};
 
 use super::bindings::consts::*;
 use super::bindings::types::*;
+use crate::force_feedback::{Condition, Effect, Envelope};
 
 pub struct SafeSetup {
     pub bustype: u16,
@@ -183,10 +187,36 @@ impl Drop for InputEvent {
 }
 
 pub enum ForceFeedback {
-    Rumble { large_motor: u16, small_motor: u16 },
+    Supported {
+        effect: Effect,
+        direction: u16,
+        delay: Duration,
+        /// `None` plays until stopped.
+        length: Option<Duration>,
+    },
     Unsupported,
 }
 
+fn envelope(envelope: &ff_envelope) -> Envelope {
+    Envelope {
+        attack_length: Duration::from_millis(envelope.attack_length as u64),
+        attack_level: envelope.attack_level,
+        fade_length: Duration::from_millis(envelope.fade_length as u64),
+        fade_level: envelope.fade_level,
+    }
+}
+
+fn condition(condition: &ff_condition_effect) -> Condition {
+    Condition {
+        right_saturation: condition.right_saturation,
+        left_saturation: condition.left_saturation,
+        right_coefficient: condition.right_coeff,
+        left_coefficient: condition.left_coeff,
+        deadband: condition.deadband,
+        center: condition.center,
+    }
+}
+
 pub struct ForceFeedbackUpload {
     layout: Layout,
     raw_ptr: *mut u8,
@@ -217,15 +247,36 @@ impl ForceFeedbackUpload {
     pub fn get_data(&self) -> (u32, ForceFeedback) {
         let ptr = self.raw_ptr as *const uinput_ff_upload;
         unsafe {
-            let force_feedback = if (*ptr).effect.type_ == FF_RUMBLE as u16 {
-                ForceFeedback::Rumble {
-                    large_motor: (*ptr).effect.u.rumble.strong_magnitude,
-                    small_motor: (*ptr).effect.u.rumble.weak_magnitude,
-                }
-            } else {
-                ForceFeedback::Unsupported
+            let raw = &(*ptr).effect;
+            // Conditions have one set of parameters per axis. Wheels only
+            // have the first one.
+            let effect = match raw.type_ as u32 {
+                FF_RUMBLE => Some(Effect::Rumble {
+                    large_motor: raw.u.rumble.strong_magnitude,
+                    small_motor: raw.u.rumble.weak_magnitude,
+                }),
+                FF_CONSTANT => Some(Effect::Constant {
+                    level: raw.u.constant.level,
+                    envelope: envelope(&raw.u.constant.envelope),
+                }),
+                FF_SPRING => Some(Effect::Spring(condition(&raw.u.condition[0]))),
+                FF_DAMPER => Some(Effect::Damper(condition(&raw.u.condition[0]))),
+                _ => None,
+            };
+
+            let force_feedback = match effect {
+                Some(effect) => ForceFeedback::Supported {
+                    effect,
+                    direction: raw.direction,
+                    delay: Duration::from_millis(raw.replay.delay as u64),
+                    length: match raw.replay.length {
+                        0 => None,
+                        length => Some(Duration::from_millis(length as u64)),
+                    },
+                },
+                None => ForceFeedback::Unsupported,
             };
-            ((*ptr).effect.id as u32, force_feedback)
+            (raw.id as u32, force_feedback)
         }
     }
 }
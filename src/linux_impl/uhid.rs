@@ -0,0 +1,331 @@
+use std::{
+    collections::VecDeque,
+    ffi::CStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+};
+
+use super::{DeviceFD, UInputFD};
+use crate::api::*;
+
+// Event and report types of linux/uhid.h.
+const UHID_DESTROY: u32 = 1;
+const UHID_START: u32 = 2;
+const UHID_STOP: u32 = 3;
+const UHID_OPEN: u32 = 4;
+const UHID_CLOSE: u32 = 5;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+const UHID_FEATURE_REPORT: u8 = 0;
+const UHID_OUTPUT_REPORT: u8 = 1;
+
+const UHID_DATA_MAX: usize = 4096;
+/// The size of `struct uhid_event`, whose largest member is the create2
+/// request.
+const UHID_EVENT_SIZE: usize = 4 + 128 + 64 + 64 + 2 + 2 + 4 * 4 + UHID_DATA_MAX;
+
+const BUS_USB: u16 = 0x03;
+
+/// How long the driver gets to start probing the device after it was created.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+/// The driver is done probing once it has not asked for anything for this
+/// long.
+const PROBE_IDLE: Duration = Duration::from_millis(100);
+
+/// A device that is emulated as a HID device, down to its reports. Inputs are
+/// applied to its input report, which is sent whole after every change.
+pub(super) trait HidProfile: Send {
+    fn name(&self) -> &str;
+
+    /// The vendor, product and version of the device.
+    fn ids(&self) -> (u32, u32, u32);
+
+    fn descriptor(&self) -> &[u8];
+
+    /// Applies an event the way it would be written to a uinput device.
+    fn apply(&mut self, r#type: u16, code: u16, value: i32);
+
+    fn motion(&mut self, _accel: [f32; 3], _gyro: [f32; 3]) -> Result<(), Error> {
+        Err(Error::InvalidInput(InvalidInput::NoMotionSensors))
+    }
+
+    fn touch(&mut self, _finger: u8, _x: f32, _y: f32) -> Result<(), Error> {
+        Err(Error::InvalidInput(InvalidInput::NoTouchpad))
+    }
+
+    fn touch_up(&mut self, _finger: u8) -> Result<(), Error> {
+        Err(Error::InvalidInput(InvalidInput::NoTouchpad))
+    }
+
+    fn touchpad_click(&mut self, _pressed: bool) -> Result<(), Error> {
+        Err(Error::InvalidInput(InvalidInput::NoTouchpad))
+    }
+
+    /// Lifts every finger off the touchpad and releases its click.
+    fn release_touchpad(&mut self) {}
+
+    /// Sets the battery to `level` percent, which is at most 100.
+    fn set_battery(&mut self, _level: u8, _charging: bool) -> Result<(), Error> {
+        Err(Error::Unsupported(format!(
+            "A {} has no battery",
+            self.name()
+        )))
+    }
+
+    /// The input report with the current state, starting with its report id.
+    fn input_report(&mut self) -> Vec<u8>;
+
+    /// What an output report the driver sent stands for.
+    fn output(&mut self, report: &[u8]) -> Vec<Output>;
+
+    /// Input reports that answer the output reports the driver sent, for
+    /// devices that are talked to through reports of their own.
+    fn take_replies(&mut self) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+
+    /// How often the input report is sent even if nothing changed, for
+    /// drivers that expect a steady stream of reports.
+    fn report_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Answers the driver asking for feature report `number`. `None` fails
+    /// the request.
+    fn feature_report(&self, number: u8) -> Option<Vec<u8>>;
+}
+
+/// Writes `value` into a report at `offset`, least significant byte first.
+pub(super) fn put_i16(report: &mut [u8], offset: usize, value: i16) {
+    report[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Scales a motion sensor reading by `resolution` and clamps it to what fits
+/// in a report.
+pub(super) fn raw_sensor(value: f32, resolution: f32) -> Result<i16, Error> {
+    if value.is_nan() {
+        return Err(Error::InvalidInput(InvalidInput::NotANumber));
+    }
+    Ok((value * resolution)
+        .round()
+        .clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+}
+
+/// An event of `r#type` with room for `size` bytes after the type.
+fn new_event(r#type: u32, size: usize) -> Vec<u8> {
+    // uhid rejects writes shorter than 6 bytes.
+    let mut event = vec![0; 4 + size.max(2)];
+    event[..4].copy_from_slice(&r#type.to_ne_bytes());
+    event
+}
+
+/// Copies `value` into `field`, truncated so that it stays nul terminated.
+fn put_str(field: &mut [u8], value: &[u8]) {
+    let n = value.len().min(field.len() - 1);
+    field[..n].copy_from_slice(&value[..n]);
+}
+
+fn write_event(fd: i32, event: &[u8]) -> Result<(), Error> {
+    let n = unsafe { libc::write(fd, event.as_ptr() as *const libc::c_void, event.len()) };
+    Errno::result(n).map_with_vgp_error()?;
+    Ok(())
+}
+
+fn u16_at(event: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([event[offset], event[offset + 1]])
+}
+
+fn u32_at(event: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes([
+        event[offset],
+        event[offset + 1],
+        event[offset + 2],
+        event[offset + 3],
+    ])
+}
+
+/// Creates a HID device for `profile`, and answers the requests its driver
+/// makes while probing it. Outputs the driver sends on the way are returned,
+/// since nobody reads the device's outputs yet.
+pub(super) fn create(
+    profile: Box<dyn HidProfile>,
+    phys: &CStr,
+    uniq: &str,
+) -> Result<(Arc<DeviceFD>, VecDeque<Output>), Error> {
+    let fd = UInputFD::open("/dev/uhid").map_with_vgp_error()?;
+
+    let descriptor = profile.descriptor();
+    let (vendor, product, version) = profile.ids();
+    let mut event = new_event(UHID_CREATE2, 276 + descriptor.len());
+    put_str(&mut event[4..132], profile.name().as_bytes());
+    put_str(&mut event[132..196], phys.to_bytes());
+    put_str(&mut event[196..260], uniq.as_bytes());
+    event[260..262].copy_from_slice(&(descriptor.len() as u16).to_ne_bytes());
+    event[262..264].copy_from_slice(&BUS_USB.to_ne_bytes());
+    event[264..268].copy_from_slice(&vendor.to_ne_bytes());
+    event[268..272].copy_from_slice(&product.to_ne_bytes());
+    event[272..276].copy_from_slice(&version.to_ne_bytes());
+    event[280..].copy_from_slice(descriptor);
+    write_event(fd.0, &event)?;
+
+    let interval = profile.report_interval();
+    let fd = Arc::new(DeviceFD::new_hid(fd, profile));
+    if let Some(interval) = interval {
+        stream(&fd, interval);
+    }
+    let pending = settle(&fd)?;
+    // Drivers learn about the initial state, the battery included, without
+    // having to ask for it while nobody serves the device.
+    fd.write_report(&[])?;
+    Ok((fd, pending))
+}
+
+/// Sends the input report of the device every `interval` until it is gone.
+fn stream(fd: &Arc<DeviceFD>, interval: Duration) {
+    let fd = Arc::downgrade(fd);
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        match fd.upgrade() {
+            Some(fd) if fd.write_report(&[]).is_ok() => {}
+            _ => return,
+        }
+    });
+}
+
+/// Serves the driver until it is done probing the device. Drivers give up on
+/// devices that do not answer them in time.
+fn settle(fd: &DeviceFD) -> Result<VecDeque<Output>, Error> {
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let mut probing = false;
+    let mut pending = VecDeque::new();
+
+    loop {
+        let timeout = if probing {
+            PROBE_IDLE
+        } else {
+            deadline.saturating_duration_since(Instant::now())
+        };
+
+        let mut poll_fds = [PollFd::new(fd.raw(), PollFlags::POLLIN)];
+        match poll(&mut poll_fds, timeout.as_millis() as i32) {
+            Ok(0) => return Ok(pending),
+            Ok(_) => {}
+            Err(nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => return Err(Error::Internal(e)),
+        }
+
+        if let Some(outputs) = serve(fd)? {
+            probing = true;
+            pending.extend(outputs);
+        }
+    }
+}
+
+/// Reads the next event of the device and answers it if it is a request.
+/// Returns the outputs it stands for, or `None` once there are no more events
+/// to read.
+pub(super) fn serve(fd: &DeviceFD) -> Result<Option<Vec<Output>>, Error> {
+    let hid = match &fd.hid {
+        Some(hid) => hid,
+        None => return Ok(None),
+    };
+
+    let mut event = vec![0u8; UHID_EVENT_SIZE];
+    let n = unsafe {
+        libc::read(
+            fd.raw(),
+            event.as_mut_ptr() as *mut libc::c_void,
+            event.len(),
+        )
+    };
+    match Errno::result(n) {
+        Ok(_) => {}
+        Err(nix::Error::Sys(Errno::EAGAIN)) => return Ok(None),
+        Err(e) => return Err(Error::Internal(e)),
+    }
+
+    let outputs = match u32_at(&event, 0) {
+        UHID_START | UHID_STOP | UHID_OPEN | UHID_CLOSE => Vec::new(),
+        UHID_OUTPUT => {
+            let size = (u16_at(&event, 4 + UHID_DATA_MAX) as usize).min(UHID_DATA_MAX);
+            let r#type = event[4 + UHID_DATA_MAX + 2];
+            if r#type == UHID_OUTPUT_REPORT {
+                output(fd.raw(), &mut **hid.lock().unwrap(), &event[4..4 + size])?
+            } else {
+                Vec::new()
+            }
+        }
+        UHID_GET_REPORT => {
+            let id = u32_at(&event, 4);
+            let report = if event[9] == UHID_FEATURE_REPORT {
+                hid.lock().unwrap().feature_report(event[8])
+            } else {
+                None
+            };
+            reply_get_report(fd.raw(), id, report)?;
+            Vec::new()
+        }
+        UHID_SET_REPORT => {
+            let id = u32_at(&event, 4);
+            let size = (u16_at(&event, 10) as usize).min(UHID_DATA_MAX);
+            let outputs = if event[9] == UHID_OUTPUT_REPORT {
+                output(fd.raw(), &mut **hid.lock().unwrap(), &event[12..12 + size])?
+            } else {
+                Vec::new()
+            };
+            let mut reply = new_event(UHID_SET_REPORT_REPLY, 6);
+            reply[4..8].copy_from_slice(&id.to_ne_bytes());
+            write_event(fd.raw(), &reply)?;
+            outputs
+        }
+        r#type => {
+            log::warn!("Got an unsupported uhid event: {}", r#type);
+            vec![Output::Unsupported]
+        }
+    };
+
+    Ok(Some(outputs))
+}
+
+/// Hands an output report to the profile and sends the replies it has.
+fn output(fd: i32, hid: &mut dyn HidProfile, report: &[u8]) -> Result<Vec<Output>, Error> {
+    let outputs = hid.output(report);
+    for reply in hid.take_replies() {
+        write_input(fd, &reply)?;
+    }
+    Ok(outputs)
+}
+
+fn reply_get_report(fd: i32, id: u32, report: Option<Vec<u8>>) -> Result<(), Error> {
+    let data = report.as_deref().unwrap_or(&[]);
+    let mut reply = new_event(UHID_GET_REPORT_REPLY, 8 + data.len());
+    reply[4..8].copy_from_slice(&id.to_ne_bytes());
+    if report.is_none() {
+        reply[8..10].copy_from_slice(&(libc::EIO as u16).to_ne_bytes());
+    }
+    reply[10..12].copy_from_slice(&(data.len() as u16).to_ne_bytes());
+    reply[12..].copy_from_slice(data);
+    write_event(fd, &reply)
+}
+
+pub(super) fn write_input(fd: i32, report: &[u8]) -> Result<(), Error> {
+    let mut event = new_event(UHID_INPUT2, 2 + report.len());
+    event[4..6].copy_from_slice(&(report.len() as u16).to_ne_bytes());
+    event[6..].copy_from_slice(report);
+    write_event(fd, &event)
+}
+
+pub(super) fn destroy(fd: i32) -> Result<(), Error> {
+    write_event(fd, &new_event(UHID_DESTROY, 0))
+}
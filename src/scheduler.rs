@@ -0,0 +1,299 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::api::{Error, Input, InvalidInput};
+use crate::input_macro::InputSink;
+use crate::timer::{sleep_until, PRECISE_MARGIN};
+
+/// When a scheduled input is put on the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum At {
+    /// The start of the given frame. Frame 0 starts when the scheduler is
+    /// created.
+    Frame(u64),
+    Deadline(Instant),
+}
+
+/// How late the scheduler put its inputs compared to their targets. Inputs
+/// are never put before their deadline, so there is no early drift to
+/// report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Drift {
+    pub emitted: u64,
+    pub last: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+impl Drift {
+    pub fn mean(&self) -> Duration {
+        if self.emitted == 0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(self.total.as_secs_f64() / self.emitted as f64)
+        }
+    }
+
+    fn record(&mut self, drift: Duration) {
+        self.emitted += 1;
+        self.last = drift;
+        self.max = self.max.max(drift);
+        self.total += drift;
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    /// Inputs by their deadline. The sequence number keeps inputs with the
+    /// same deadline in the order they were scheduled.
+    queue: BTreeMap<(Instant, u64), Input>,
+    next_sequence: u64,
+    drift: Drift,
+    stopped: bool,
+}
+
+/// Puts inputs on a device at exact frames or deadlines, on a fixed frame
+/// rate.
+///
+/// Inputs are put from a dedicated thread that sleeps until each deadline on
+/// the most precise timer of the platform. Inputs whose deadline has already
+/// passed are put right away.
+pub struct Scheduler {
+    start: Instant,
+    frame_rate: f64,
+    state: Arc<(Mutex<SchedulerState>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(frame_rate: f64, sink: InputSink) -> Self {
+        assert!(
+            frame_rate > 0.0 && frame_rate.is_finite(),
+            "The frame rate has to be positive, got {}",
+            frame_rate
+        );
+
+        let state = Arc::new((Mutex::new(SchedulerState::default()), Condvar::new()));
+
+        let thread = {
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || Self::run(&state, &sink))
+        };
+
+        Self {
+            start: Instant::now(),
+            frame_rate,
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(state: &(Mutex<SchedulerState>, Condvar), sink: &InputSink) {
+        let (state, condvar) = state;
+        let mut guard = state.lock().unwrap();
+
+        while !guard.stopped {
+            let deadline = match guard.queue.keys().next() {
+                Some((deadline, _)) => *deadline,
+                None => {
+                    guard = condvar.wait(guard).unwrap();
+                    continue;
+                }
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining > PRECISE_MARGIN {
+                // Wakes up early if an earlier input gets scheduled meanwhile.
+                guard = condvar
+                    .wait_timeout(guard, remaining - PRECISE_MARGIN)
+                    .unwrap()
+                    .0;
+                continue;
+            }
+
+            drop(guard);
+            sleep_until(deadline);
+            guard = state.lock().unwrap();
+
+            let due: Vec<(Instant, Input)> = {
+                let now = Instant::now();
+                let later = guard.queue.split_off(&(now, u64::MAX));
+                std::mem::replace(&mut guard.queue, later)
+                    .into_iter()
+                    .map(|((deadline, _), input)| (deadline, input))
+                    .collect()
+            };
+
+            // The sink writes to the device, which must not keep `schedule`
+            // waiting, and it may schedule more inputs itself.
+            drop(guard);
+            let drifts: Vec<Duration> = due
+                .into_iter()
+                .map(|(deadline, input)| {
+                    if let Err(e) = sink(input) {
+                        log::error!("Failed to put scheduled input: {:?}", e);
+                    }
+                    Instant::now().saturating_duration_since(deadline)
+                })
+                .collect();
+
+            guard = state.lock().unwrap();
+            for drift in drifts {
+                guard.drift.record(drift);
+            }
+        }
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frame_rate)
+    }
+
+    /// When `frame` starts. It is computed from the frame rate rather than
+    /// from the rounded frame duration, so late frames do not drift. Fails if
+    /// the frame is too far in the future for the clock.
+    pub fn frame_start(&self, frame: u64) -> Result<Instant, Error> {
+        let out_of_range = || {
+            Error::InvalidInput(InvalidInput::OutOfRange {
+                value: frame as f32,
+            })
+        };
+
+        let offset = frame as f64 / self.frame_rate;
+        if offset >= u64::MAX as f64 {
+            return Err(out_of_range());
+        }
+        self.start
+            .checked_add(Duration::from_secs_f64(offset))
+            .ok_or_else(out_of_range)
+    }
+
+    pub fn current_frame(&self) -> u64 {
+        let elapsed = Instant::now().saturating_duration_since(self.start);
+        (elapsed.as_secs_f64() * self.frame_rate) as u64
+    }
+
+    /// Fails if the input is scheduled for a frame that `frame_start` cannot
+    /// tell the start of.
+    pub fn schedule(&self, at: At, input: Input) -> Result<(), Error> {
+        let deadline = match at {
+            At::Frame(frame) => self.frame_start(frame)?,
+            At::Deadline(deadline) => deadline,
+        };
+
+        let (state, condvar) = &*self.state;
+        let mut state = state.lock().unwrap();
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        state.queue.insert((deadline, sequence), input);
+        condvar.notify_one();
+        Ok(())
+    }
+
+    /// Drops every input that was not put yet.
+    pub fn clear(&self) {
+        self.state.0.lock().unwrap().queue.clear();
+    }
+
+    pub fn pending(&self) -> usize {
+        self.state.0.lock().unwrap().queue.len()
+    }
+
+    pub fn drift(&self) -> Drift {
+        self.state.0.lock().unwrap().drift
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        {
+            let (state, condvar) = &*self.state;
+            state.lock().unwrap().stopped = true;
+            condvar.notify_one();
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Scheduler thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Button;
+
+    #[test]
+    fn mean_does_not_truncate_the_count() {
+        let drift = Drift {
+            emitted: u64::from(u32::MAX) + 1,
+            total: Duration::from_secs(1 << 32),
+            ..Default::default()
+        };
+        assert_eq!(drift.mean(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn frame_starts_do_not_accumulate_rounding() {
+        let scheduler = Scheduler::new(3.0, Arc::new(|_| Ok(())));
+        let start = scheduler.frame_start(0).unwrap();
+        assert_eq!(
+            scheduler.frame_start(3_000_000).unwrap() - start,
+            Duration::from_secs(1_000_000)
+        );
+
+        let scheduler = Scheduler::new(1.0, Arc::new(|_| Ok(())));
+        assert!(scheduler.frame_start(u64::MAX).is_err());
+        assert!(scheduler.frame_start(1 << 63).is_err());
+        assert!(scheduler
+            .schedule(At::Frame(u64::MAX), Input::Press(Button::South))
+            .is_err());
+    }
+
+    #[test]
+    fn puts_inputs_in_deadline_order() {
+        let inputs = Arc::new(Mutex::new(Vec::new()));
+        let sink: InputSink = {
+            let inputs = Arc::clone(&inputs);
+            Arc::new(move |input| {
+                inputs.lock().unwrap().push((Instant::now(), input));
+                Ok(())
+            })
+        };
+
+        let scheduler = Scheduler::new(1000.0, sink);
+        let schedule = |frame, input| scheduler.schedule(At::Frame(frame), input).unwrap();
+        schedule(20, Input::Release(Button::South));
+        schedule(10, Input::Press(Button::South));
+        schedule(10, Input::Press(Button::East));
+
+        let timeout = Instant::now() + Duration::from_secs(10);
+        while scheduler.drift().emitted < 3 {
+            assert!(Instant::now() < timeout, "The inputs were never put");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let inputs = inputs.lock().unwrap();
+        assert_eq!(
+            inputs
+                .iter()
+                .map(|(_, input)| input.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Input::Press(Button::South),
+                Input::Press(Button::East),
+                Input::Release(Button::South),
+            ]
+        );
+        // Only lateness depends on the load of the machine running the test,
+        // so that is all that gets a generous margin.
+        for ((put, _), frame) in inputs.iter().zip([10, 10, 20].iter()) {
+            assert!(*put >= scheduler.frame_start(*frame).unwrap());
+        }
+        assert!(scheduler.drift().max < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,40 @@
+/// High-resolution wheel units per notch, as the kernel counts them.
+pub(crate) const UNITS_PER_NOTCH: i32 = 120;
+
+/// The scrolling of a mouse. It is put in high-resolution units, and
+/// consumers that only know whole notches see one each time a notch worth of
+/// them has piled up.
+#[derive(Debug, Default)]
+pub(crate) struct ScrollAccumulator {
+    /// The units since the last whole notch, vertically and horizontally.
+    remainder: [i32; 2],
+}
+
+impl ScrollAccumulator {
+    /// Scrolls by `vertical` and `horizontal` units and returns the whole
+    /// notches that were completed along each.
+    pub fn scroll(&mut self, vertical: i32, horizontal: i32) -> [i32; 2] {
+        let mut notches = [0; 2];
+        for (i, units) in [vertical, horizontal].iter().enumerate() {
+            self.remainder[i] = self.remainder[i].saturating_add(*units);
+            notches[i] = self.remainder[i] / UNITS_PER_NOTCH;
+            self.remainder[i] -= notches[i] * UNITS_PER_NOTCH;
+        }
+        notches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn piles_up_units_into_notches() {
+        let mut scroll = ScrollAccumulator::default();
+        assert_eq!(scroll.scroll(60, 0), [0, 0]);
+        assert_eq!(scroll.scroll(60, -120), [1, -1]);
+        assert_eq!(scroll.scroll(-30, 0), [0, 0]);
+        assert_eq!(scroll.scroll(-90, 0), [-1, 0]);
+        assert_eq!(scroll.scroll(250, 0), [2, 0]);
+    }
+}
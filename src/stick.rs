@@ -0,0 +1,217 @@
+use crate::api::ThumbStick;
+
+/// How the inner deadzone of a thumb stick is shaped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Deadzone {
+    /// No deadzone. The remaining stages are applied to each axis on its own.
+    None,
+    /// Each axis is treated on its own. Makes it easy to hold a perfectly
+    /// straight direction, at the cost of snapping near the axes.
+    Axial(f32),
+    /// The deadzone is a circle around the center. Keeps the direction of
+    /// the stick intact.
+    Radial(f32),
+}
+
+/// Maps the normalized stick magnitude (`0.0..=1.0`) to the output magnitude.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseCurve {
+    Linear,
+    /// `output = input ^ exponent`. Values above 1 give finer control near
+    /// the center.
+    Exponential(f32),
+    /// Output samples evenly spaced over the input range, linearly
+    /// interpolated. The first sample is at 0 and the last one at 1.
+    Lut(Vec<f32>),
+}
+
+/// The processing applied to a thumb stick before its position is written to
+/// the device.
+///
+/// The stages run in this order: deadzone, outer saturation, response curve
+/// and then anti-deadzone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StickProcessing {
+    pub deadzone: Deadzone,
+    /// The magnitude the output jumps to as soon as the stick leaves the
+    /// deadzone. Used to cancel out the deadzone the game applies on its own.
+    pub anti_deadzone: f32,
+    /// The magnitude at which the output is already at its maximum.
+    pub outer_saturation: f32,
+    pub curve: ResponseCurve,
+}
+
+impl Default for StickProcessing {
+    fn default() -> Self {
+        Self {
+            deadzone: Deadzone::None,
+            anti_deadzone: 0.0,
+            outer_saturation: 1.0,
+            curve: ResponseCurve::Linear,
+        }
+    }
+}
+
+impl StickProcessing {
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        match self.deadzone {
+            Deadzone::None => (self.apply_axis(x, 0.0), self.apply_axis(y, 0.0)),
+            Deadzone::Axial(deadzone) => {
+                (self.apply_axis(x, deadzone), self.apply_axis(y, deadzone))
+            }
+            Deadzone::Radial(deadzone) => self.apply_radial(x, y, deadzone),
+        }
+    }
+
+    fn apply_radial(&self, x: f32, y: f32, deadzone: f32) -> (f32, f32) {
+        let magnitude = (x * x + y * y).sqrt();
+        if magnitude == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let scale = self.map_magnitude(magnitude, deadzone) / magnitude;
+        (x * scale, y * scale)
+    }
+
+    fn apply_axis(&self, value: f32, deadzone: f32) -> f32 {
+        self.map_magnitude(value.abs(), deadzone).copysign(value)
+    }
+
+    fn map_magnitude(&self, magnitude: f32, deadzone: f32) -> f32 {
+        if magnitude <= deadzone {
+            return 0.0;
+        }
+
+        let range = self.outer_saturation - deadzone;
+        let magnitude = if range > 0.0 {
+            ((magnitude - deadzone) / range).min(1.0)
+        } else {
+            1.0
+        };
+        let magnitude = self.curve.apply(magnitude);
+
+        // Curves and anti-deadzones can be configured to overshoot, but the
+        // value has to stay within the range of the axis it ends up on.
+        (self.anti_deadzone + magnitude * (1.0 - self.anti_deadzone)).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StickProcessors {
+    left: StickProcessing,
+    right: StickProcessing,
+}
+
+impl StickProcessors {
+    pub fn get(&self, thumb_stick: ThumbStick) -> &StickProcessing {
+        match thumb_stick {
+            ThumbStick::Left => &self.left,
+            ThumbStick::Right => &self.right,
+        }
+    }
+
+    pub fn set(&mut self, thumb_stick: ThumbStick, processing: StickProcessing) {
+        match thumb_stick {
+            ThumbStick::Left => self.left = processing,
+            ThumbStick::Right => self.right = processing,
+        }
+    }
+}
+
+impl ResponseCurve {
+    fn apply(&self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Exponential(exponent) => value.powf(*exponent),
+            ResponseCurve::Lut(samples) => match samples.len() {
+                0 => value,
+                1 => samples[0],
+                len => {
+                    let position = value * (len - 1) as f32;
+                    let index = (position as usize).min(len - 2);
+                    let fraction = position - index as f32;
+                    samples[index] + (samples[index + 1] - samples[index]) * fraction
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx_eq(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn default_processing_is_identity() {
+        let processing = StickProcessing::default();
+        let (x, y) = processing.apply(0.3, -0.4);
+        assert!(approx_eq(x, 0.3) && approx_eq(y, -0.4));
+    }
+
+    #[test]
+    fn radial_deadzone_rescales_the_remaining_range() {
+        let processing = StickProcessing {
+            deadzone: Deadzone::Radial(0.2),
+            ..Default::default()
+        };
+        assert_eq!(processing.apply(0.1, 0.1), (0.0, 0.0));
+
+        let (x, y) = processing.apply(0.6, 0.0);
+        assert!(approx_eq(x, 0.5) && approx_eq(y, 0.0));
+    }
+
+    #[test]
+    fn axial_deadzone_keeps_the_other_axis() {
+        let processing = StickProcessing {
+            deadzone: Deadzone::Axial(0.2),
+            ..Default::default()
+        };
+        let (x, y) = processing.apply(0.1, -1.0);
+        assert!(approx_eq(x, 0.0) && approx_eq(y, -1.0));
+    }
+
+    #[test]
+    fn anti_deadzone_and_saturation() {
+        let processing = StickProcessing {
+            anti_deadzone: 0.25,
+            outer_saturation: 0.8,
+            ..Default::default()
+        };
+        let (x, _) = processing.apply(0.4, 0.0);
+        assert!(approx_eq(x, 0.625));
+
+        let (x, _) = processing.apply(0.9, 0.0);
+        assert!(approx_eq(x, 1.0));
+    }
+
+    #[test]
+    fn output_never_leaves_the_unit_range() {
+        let processing = StickProcessing {
+            anti_deadzone: 0.5,
+            curve: ResponseCurve::Lut(vec![0.0, 2.0]),
+            ..Default::default()
+        };
+        let (x, y) = processing.apply(1.0, -0.25);
+        assert!(approx_eq(x, 1.0) && approx_eq(y, -0.75));
+
+        let processing = StickProcessing {
+            deadzone: Deadzone::Radial(0.1),
+            curve: ResponseCurve::Lut(vec![0.0, 3.0]),
+            ..Default::default()
+        };
+        let (x, y) = processing.apply(1.0, 1.0);
+        assert!(x.hypot(y) <= 1.0 + 1e-5);
+    }
+
+    #[test]
+    fn lut_curve_interpolates_between_samples() {
+        let curve = ResponseCurve::Lut(vec![0.0, 0.1, 1.0]);
+        assert!(approx_eq(curve.apply(0.25), 0.05));
+        assert!(approx_eq(curve.apply(0.75), 0.55));
+        assert!(approx_eq(curve.apply(1.0), 1.0));
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How long before a deadline the waiting thread stops waiting on a condition
+/// variable and switches to `sleep_until`. Condition variable timeouts tend to
+/// overshoot by about this much.
+pub(crate) const PRECISE_MARGIN: Duration = Duration::from_millis(2);
+
+/// Sleeps until `deadline` with an absolute `clock_nanosleep` on
+/// `CLOCK_MONOTONIC`, which does not accumulate the error a relative sleep
+/// would.
+#[cfg(target_os = "linux")]
+pub(crate) fn sleep_until(deadline: Instant) {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining == Duration::from_secs(0) {
+        return;
+    }
+
+    let mut target = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut target);
+    }
+    let nanos = target.tv_nsec as u64 + remaining.subsec_nanos() as u64;
+    target.tv_sec += remaining.as_secs() as libc::time_t + (nanos / 1_000_000_000) as libc::time_t;
+    target.tv_nsec = (nanos % 1_000_000_000) as libc::c_long;
+
+    loop {
+        let result = unsafe {
+            libc::clock_nanosleep(
+                libc::CLOCK_MONOTONIC,
+                libc::TIMER_ABSTIME,
+                &target,
+                std::ptr::null_mut(),
+            )
+        };
+        if result != libc::EINTR {
+            break;
+        }
+    }
+}
+
+/// Sleeps until shortly before `deadline` and spins for the rest, since the
+/// platform's sleeps are not precise enough on their own.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn sleep_until(deadline: Instant) {
+    const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining > SPIN_MARGIN {
+        std::thread::sleep(remaining - SPIN_MARGIN);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// A flag that can be raised from another thread to wake up whoever waits on
+/// it.
+#[derive(Default)]
+pub(crate) struct Cancellation {
+    cancelled: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        *self.cancelled.lock().unwrap() = true;
+        self.condvar.notify_all();
+    }
+
+    /// Waits until `deadline`. Returns `false` if cancelled before that.
+    pub fn wait_until(&self, deadline: Instant) -> bool {
+        let mut cancelled = self.cancelled.lock().unwrap();
+        loop {
+            if *cancelled {
+                return false;
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining <= PRECISE_MARGIN {
+                break;
+            }
+            cancelled = self
+                .condvar
+                .wait_timeout(cancelled, remaining - PRECISE_MARGIN)
+                .unwrap()
+                .0;
+        }
+        drop(cancelled);
+
+        sleep_until(deadline);
+        true
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::api::{Button, Error};
+
+/// Autofire settings of a button. While the button is held, it is pressed and
+/// released `rate` times per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Turbo {
+    /// Press and release cycles per second. A rate that is not positive holds
+    /// the button down like a normal press.
+    pub rate: f32,
+    /// The fraction of each cycle the button is pressed, in `0.0..=1.0`.
+    pub duty_cycle: f32,
+}
+
+impl Default for Turbo {
+    fn default() -> Self {
+        Self {
+            rate: 10.0,
+            duty_cycle: 0.5,
+        }
+    }
+}
+
+impl Turbo {
+    /// Whether the button is pressed `elapsed` after it was first pressed, and
+    /// how long it stays that way.
+    fn phase(&self, elapsed: Duration) -> (bool, Option<Duration>) {
+        if !(self.rate > 0.0 && self.rate.is_finite()) {
+            return (true, None);
+        }
+
+        let period = 1.0 / self.rate as f64;
+        let pressed_for = period * (self.duty_cycle as f64).clamp(0.0, 1.0);
+        let position = elapsed.as_secs_f64() % period;
+
+        if position < pressed_for {
+            (true, Some(Duration::from_secs_f64(pressed_for - position)))
+        } else {
+            (false, Some(Duration::from_secs_f64(period - position)))
+        }
+    }
+}
+
+/// Writes a press (`true`) or a release (`false`) of a button to the device.
+pub(crate) type ButtonSink = Arc<dyn Fn(Button, bool) -> Result<(), Error> + Send + Sync>;
+
+struct HeldButton {
+    turbo: Turbo,
+    since: Instant,
+    pressed: bool,
+}
+
+#[derive(Default)]
+struct TurboState {
+    turbos: HashMap<Button, Turbo>,
+    held: HashMap<Button, HeldButton>,
+    stopped: bool,
+}
+
+/// Runs the autofire of every held turbo button on its own thread, so that
+/// the timing does not depend on how often the caller puts inputs.
+pub(crate) struct TurboWorker {
+    state: Arc<(Mutex<TurboState>, Condvar)>,
+    sink: ButtonSink,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TurboWorker {
+    pub fn new(sink: ButtonSink) -> Self {
+        let state = Arc::new((Mutex::new(TurboState::default()), Condvar::new()));
+
+        let thread = {
+            let state = Arc::clone(&state);
+            let sink = Arc::clone(&sink);
+            std::thread::spawn(move || Self::run(&state, &sink))
+        };
+
+        Self {
+            state,
+            sink,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(state: &(Mutex<TurboState>, Condvar), sink: &ButtonSink) {
+        let (state, condvar) = state;
+        let mut state = state.lock().unwrap();
+
+        while !state.stopped {
+            let now = Instant::now();
+            let mut next_change: Option<Duration> = None;
+
+            for (button, held) in state.held.iter_mut() {
+                let (pressed, remaining) = held.turbo.phase(now - held.since);
+                if pressed != held.pressed {
+                    if let Err(e) = sink(*button, pressed) {
+                        log::error!("Failed to write turbo input of {:?}: {:?}", button, e);
+                    }
+                    held.pressed = pressed;
+                }
+                if let Some(remaining) = remaining {
+                    next_change = Some(next_change.map_or(remaining, |n| n.min(remaining)));
+                }
+            }
+
+            state = match next_change {
+                Some(timeout) => condvar.wait_timeout(state, timeout).unwrap().0,
+                None => condvar.wait(state).unwrap(),
+            };
+        }
+    }
+
+    pub fn set(&self, button: Button, turbo: Option<Turbo>) -> Result<(), Error> {
+        let (state, condvar) = &*self.state;
+        let mut state = state.lock().unwrap();
+
+        match turbo {
+            Some(turbo) => {
+                state.turbos.insert(button, turbo);
+                if let Some(held) = state.held.get_mut(&button) {
+                    held.turbo = turbo;
+                }
+            }
+            None => {
+                state.turbos.remove(&button);
+                // A button that is still held stays pressed without autofire.
+                if let Some(held) = state.held.remove(&button) {
+                    if !held.pressed {
+                        (self.sink)(button, true)?;
+                    }
+                }
+            }
+        }
+
+        condvar.notify_one();
+        Ok(())
+    }
+
+    /// Starts the autofire of `button`. Returns `false` if the button has no
+    /// turbo, in which case the caller has to press it on its own.
+    pub fn press(&self, button: Button) -> Result<bool, Error> {
+        let (state, condvar) = &*self.state;
+        let mut state = state.lock().unwrap();
+
+        let turbo = match state.turbos.get(&button) {
+            Some(turbo) => *turbo,
+            None => return Ok(false),
+        };
+        if state.held.contains_key(&button) {
+            return Ok(true);
+        }
+
+        let (pressed, _) = turbo.phase(Duration::from_secs(0));
+        if pressed {
+            (self.sink)(button, true)?;
+        }
+        state.held.insert(
+            button,
+            HeldButton {
+                turbo,
+                since: Instant::now(),
+                pressed,
+            },
+        );
+
+        condvar.notify_one();
+        Ok(true)
+    }
+
+    /// Stops the autofire of `button`. Returns `false` if the autofire was not
+    /// running, like for a button that was pressed before its turbo was set,
+    /// in which case the caller has to release it on its own.
+    pub fn release(&self, button: Button) -> Result<bool, Error> {
+        let (state, condvar) = &*self.state;
+        let mut state = state.lock().unwrap();
+
+        let held = match state.held.remove(&button) {
+            Some(held) => held,
+            None => return Ok(false),
+        };
+        if held.pressed {
+            (self.sink)(button, false)?;
+        }
+
+        condvar.notify_one();
+        Ok(true)
+    }
+}
+
+impl Drop for TurboWorker {
+    fn drop(&mut self) {
+        {
+            let (state, condvar) = &*self.state;
+            state.lock().unwrap().stopped = true;
+            condvar.notify_one();
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Turbo thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_follows_the_duty_cycle() {
+        let turbo = Turbo {
+            rate: 10.0,
+            duty_cycle: 0.25,
+        };
+
+        let (pressed, remaining) = turbo.phase(Duration::from_millis(10));
+        assert!(pressed);
+        assert!((remaining.unwrap().as_secs_f64() - 0.015).abs() < 1e-9);
+
+        let (pressed, remaining) = turbo.phase(Duration::from_millis(130));
+        assert!(!pressed);
+        assert!((remaining.unwrap().as_secs_f64() - 0.07).abs() < 1e-9);
+    }
+
+    #[test]
+    fn leaves_buttons_pressed_before_the_turbo_to_the_caller() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink: ButtonSink = {
+            let events = Arc::clone(&events);
+            Arc::new(move |button, pressed| {
+                events.lock().unwrap().push((button, pressed));
+                Ok(())
+            })
+        };
+        let worker = TurboWorker::new(sink);
+        let hold = Turbo {
+            rate: 0.0,
+            duty_cycle: 0.5,
+        };
+
+        // South was pressed by the caller before its turbo was set.
+        worker.set(Button::South, Some(hold)).unwrap();
+        assert!(!worker.release(Button::South).unwrap());
+        assert!(events.lock().unwrap().is_empty());
+
+        assert!(worker.press(Button::South).unwrap());
+        assert!(worker.release(Button::South).unwrap());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![(Button::South, true), (Button::South, false)]
+        );
+    }
+
+    #[test]
+    fn zero_rate_holds_the_button() {
+        let turbo = Turbo {
+            rate: 0.0,
+            duty_cycle: 0.5,
+        };
+        assert_eq!(turbo.phase(Duration::from_secs(3)), (true, None));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::api::{Axis, AxisInfo, InvalidInput};
+
+/// What to do with thumb stick values that fall outside of `-1.0..=1.0`.
+///
+/// NaN is never accepted, whatever the policy is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValuePolicy {
+    /// Each axis is clamped to `-1.0..=1.0` on its own.
+    Clamp,
+    /// Values outside of `-1.0..=1.0` are rejected with
+    /// `InvalidInput::OutOfRange`.
+    Reject,
+    /// A vector longer than 1 is scaled back onto the unit circle, keeping its
+    /// direction.
+    Normalize,
+}
+
+// Deriving this needs `#[default]` on the variant, which is only stable since
+// Rust 1.62.
+#[allow(clippy::derivable_impls)]
+impl Default for ValuePolicy {
+    fn default() -> Self {
+        ValuePolicy::Clamp
+    }
+}
+
+impl ValuePolicy {
+    pub fn apply(self, x: f32, y: f32) -> Result<(f32, f32), InvalidInput> {
+        if x.is_nan() || y.is_nan() {
+            return Err(InvalidInput::NotANumber);
+        }
+
+        match self {
+            ValuePolicy::Clamp => Ok((x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0))),
+            ValuePolicy::Reject => {
+                for value in [x, y].iter() {
+                    if value.abs() > 1.0 {
+                        return Err(InvalidInput::OutOfRange { value: *value });
+                    }
+                }
+                Ok((x, y))
+            }
+            ValuePolicy::Normalize => {
+                let magnitude = x.hypot(y);
+                if magnitude.is_infinite() {
+                    Err(InvalidInput::OutOfRange { value: magnitude })
+                } else if magnitude > 1.0 {
+                    Ok((x / magnitude, y / magnitude))
+                } else {
+                    Ok((x, y))
+                }
+            }
+        }
+    }
+
+    /// Checks a raw axis value against the axis' range. There is no vector to
+    /// normalize, so `Normalize` clamps like `Clamp` does.
+    pub fn apply_raw(self, axis: Axis, value: i32, info: &AxisInfo) -> Result<i32, InvalidInput> {
+        match self {
+            ValuePolicy::Clamp | ValuePolicy::Normalize => {
+                Ok(value.clamp(info.minimum, info.maximum))
+            }
+            ValuePolicy::Reject => {
+                if value < info.minimum || value > info.maximum {
+                    Err(InvalidInput::AxisOutOfRange { axis, value })
+                } else {
+                    Ok(value)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nan_is_always_rejected() {
+        for policy in [
+            ValuePolicy::Clamp,
+            ValuePolicy::Reject,
+            ValuePolicy::Normalize,
+        ]
+        .iter()
+        {
+            assert_eq!(policy.apply(f32::NAN, 0.0), Err(InvalidInput::NotANumber));
+        }
+    }
+
+    #[test]
+    fn clamp_limits_each_axis() {
+        assert_eq!(ValuePolicy::Clamp.apply(1.5, -3.0), Ok((1.0, -1.0)));
+        assert_eq!(ValuePolicy::Clamp.apply(f32::INFINITY, 0.5), Ok((1.0, 0.5)));
+    }
+
+    #[test]
+    fn reject_returns_the_offending_value() {
+        assert_eq!(ValuePolicy::Reject.apply(1.0, -1.0), Ok((1.0, -1.0)));
+        assert_eq!(
+            ValuePolicy::Reject.apply(0.0, -1.25),
+            Err(InvalidInput::OutOfRange { value: -1.25 })
+        );
+    }
+
+    #[test]
+    fn normalize_keeps_the_direction() {
+        assert_eq!(ValuePolicy::Normalize.apply(0.3, 0.4), Ok((0.3, 0.4)));
+
+        let (x, y) = ValuePolicy::Normalize.apply(3.0, -4.0).unwrap();
+        assert!((x - 0.6).abs() < 1e-6 && (y + 0.8).abs() < 1e-6);
+
+        assert!(ValuePolicy::Normalize.apply(f32::INFINITY, 0.0).is_err());
+    }
+
+    #[test]
+    fn raw_values_are_checked_against_the_axis_range() {
+        let info = AxisInfo {
+            minimum: -512,
+            maximum: 512,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        };
+        assert_eq!(ValuePolicy::Clamp.apply_raw(Axis::X, 600, &info), Ok(512));
+        assert_eq!(
+            ValuePolicy::Reject.apply_raw(Axis::X, -513, &info),
+            Err(InvalidInput::AxisOutOfRange {
+                axis: Axis::X,
+                value: -513
+            })
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::api::Error;
+
+/// Puts the device back into its neutral state.
+pub(crate) type ResetSink = Arc<dyn Fn() -> Result<(), Error> + Send + Sync>;
+
+struct WatchdogState {
+    timeout: Duration,
+    last_input: Instant,
+    /// Whether an input arrived since the last reset. The device is reset only
+    /// once per idle period.
+    armed: bool,
+    stopped: bool,
+}
+
+/// Resets the device when no input arrives for a while, so that inputs of a
+/// feeder that went away do not stay stuck.
+pub(crate) struct Watchdog {
+    state: Arc<(Mutex<WatchdogState>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration, sink: ResetSink) -> Self {
+        let state = Arc::new((
+            Mutex::new(WatchdogState {
+                timeout,
+                last_input: Instant::now(),
+                armed: true,
+                stopped: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let thread = {
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || Self::run(&state, &sink))
+        };
+
+        Self {
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(state: &(Mutex<WatchdogState>, Condvar), sink: &ResetSink) {
+        let (state, condvar) = state;
+        let mut guard = state.lock().unwrap();
+
+        while !guard.stopped {
+            if !guard.armed {
+                guard = condvar.wait(guard).unwrap();
+                continue;
+            }
+
+            let deadline = guard.last_input + guard.timeout;
+            let now = Instant::now();
+            if now < deadline {
+                guard = condvar.wait_timeout(guard, deadline - now).unwrap().0;
+                continue;
+            }
+
+            guard.armed = false;
+            drop(guard);
+            if let Err(e) = sink() {
+                log::error!("Watchdog failed to reset the device: {:?}", e);
+            }
+            guard = state.lock().unwrap();
+        }
+    }
+
+    pub fn set_timeout(&self, timeout: Duration) {
+        let (state, condvar) = &*self.state;
+        state.lock().unwrap().timeout = timeout;
+        condvar.notify_one();
+    }
+
+    /// Tells the watchdog an input arrived.
+    pub fn feed(&self) {
+        let (state, condvar) = &*self.state;
+        let mut state = state.lock().unwrap();
+        state.last_input = Instant::now();
+        if !state.armed {
+            state.armed = true;
+            condvar.notify_one();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        {
+            let (state, condvar) = &*self.state;
+            state.lock().unwrap().stopped = true;
+            condvar.notify_one();
+        }
+        if let Some(thread) = self.thread.take() {
+            // The reset can end up dropping the device, and this watchdog with
+            // it, on the watchdog's own thread.
+            if thread.thread().id() == std::thread::current().id() {
+                return;
+            }
+            if thread.join().is_err() {
+                log::error!("Watchdog thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn wait_for(resets: &AtomicUsize, count: usize) {
+        let timeout = Instant::now() + Duration::from_secs(10);
+        while resets.load(Ordering::SeqCst) < count {
+            assert!(Instant::now() < timeout, "The watchdog never reset");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // The timeouts are either far longer than the test or already over, so
+    // that how fast the machine running the test is does not matter.
+    #[test]
+    fn resets_once_per_idle_period() {
+        let resets = Arc::new(AtomicUsize::new(0));
+        let sink: ResetSink = {
+            let resets = Arc::clone(&resets);
+            Arc::new(move || {
+                resets.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+        };
+
+        let watchdog = Watchdog::new(Duration::from_secs(3600), sink);
+        watchdog.feed();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(resets.load(Ordering::SeqCst), 0);
+
+        watchdog.set_timeout(Duration::from_secs(0));
+        wait_for(&resets, 1);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(resets.load(Ordering::SeqCst), 1);
+
+        watchdog.feed();
+        wait_for(&resets, 2);
+    }
+}
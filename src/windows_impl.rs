@@ -1,13 +1,106 @@
 use crate::api::*;
+use crate::config::{DeviceConfig, DeviceKind};
+use crate::input_macro::{self, InputSink, Macro, MacroHandle};
+use crate::scheduler::Scheduler;
+use crate::stick::*;
+use crate::turbo::*;
+use crate::validation::*;
+use crate::watchdog::Watchdog;
 
 use std::{
+    collections::HashSet,
     os::raw::c_short,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use vigem_client as vgm;
 use vigem_client::ClientExt;
 
+const THUMB_STICK_AXIS_INFO: AxisInfo = AxisInfo {
+    minimum: -32768,
+    maximum: 32767,
+    fuzz: 0,
+    flat: 0,
+    resolution: 0,
+};
+
+/// The emulated Xbox 360 controller has no capture button, no paddles and no
+/// numbered buttons.
+fn press_input(button: Button) -> Result<vgm::Input, Error> {
+    let input = match button {
+        Button::DpadDown => vgm::Input::Pressed(vgm::Button::DpadDown),
+        Button::DpadUp => vgm::Input::Pressed(vgm::Button::DpadUp),
+        Button::DpadLeft => vgm::Input::Pressed(vgm::Button::DpadLeft),
+        Button::DpadRight => vgm::Input::Pressed(vgm::Button::DpadRight),
+        Button::North => vgm::Input::Pressed(vgm::Button::Y),
+        Button::South => vgm::Input::Pressed(vgm::Button::A),
+        Button::West => vgm::Input::Pressed(vgm::Button::B),
+        Button::East => vgm::Input::Pressed(vgm::Button::X),
+        Button::Start => vgm::Input::Pressed(vgm::Button::Start),
+        Button::Select => vgm::Input::Pressed(vgm::Button::Back),
+        Button::TriggerLeft => vgm::Input::Pressed(vgm::Button::LeftShoulder),
+        Button::TriggerRight => vgm::Input::Pressed(vgm::Button::RightShoulder),
+        Button::TriggerLeft2 => vgm::Input::PressedLeftTrigger(127),
+        Button::TriggerRight2 => vgm::Input::PressedRightTrigger(127),
+        Button::ThumbStickLeft => vgm::Input::Pressed(vgm::Button::LeftThumb),
+        Button::ThumbStickRight => vgm::Input::Pressed(vgm::Button::RightThumb),
+        Button::Mode => vgm::Input::Pressed(vgm::Button::Guide),
+        Button::Capture
+        | Button::Paddle1
+        | Button::Paddle2
+        | Button::Paddle3
+        | Button::Paddle4
+        | Button::Numbered(_)
+        | Button::Key(_)
+        | Button::MouseLeft
+        | Button::MouseRight
+        | Button::MouseMiddle
+        | Button::MouseSide
+        | Button::MouseExtra => {
+            return Err(Error::InvalidInput(InvalidInput::UnsupportedButton(button)))
+        }
+    };
+    Ok(input)
+}
+
+fn release_input(button: Button) -> Result<vgm::Input, Error> {
+    let input = match button {
+        Button::DpadDown => vgm::Input::Released(vgm::Button::DpadDown),
+        Button::DpadUp => vgm::Input::Released(vgm::Button::DpadUp),
+        Button::DpadLeft => vgm::Input::Released(vgm::Button::DpadLeft),
+        Button::DpadRight => vgm::Input::Released(vgm::Button::DpadRight),
+        Button::North => vgm::Input::Released(vgm::Button::Y),
+        Button::South => vgm::Input::Released(vgm::Button::A),
+        Button::West => vgm::Input::Released(vgm::Button::B),
+        Button::East => vgm::Input::Released(vgm::Button::X),
+        Button::Start => vgm::Input::Released(vgm::Button::Start),
+        Button::Select => vgm::Input::Released(vgm::Button::Back),
+        Button::TriggerLeft => vgm::Input::Released(vgm::Button::LeftShoulder),
+        Button::TriggerRight => vgm::Input::Released(vgm::Button::RightShoulder),
+        Button::TriggerLeft2 => vgm::Input::PressedLeftTrigger(0),
+        Button::TriggerRight2 => vgm::Input::PressedRightTrigger(0),
+        Button::ThumbStickLeft => vgm::Input::Released(vgm::Button::LeftThumb),
+        Button::ThumbStickRight => vgm::Input::Released(vgm::Button::RightThumb),
+        Button::Mode => vgm::Input::Released(vgm::Button::Guide),
+        Button::Capture
+        | Button::Paddle1
+        | Button::Paddle2
+        | Button::Paddle3
+        | Button::Paddle4
+        | Button::Numbered(_)
+        | Button::Key(_)
+        | Button::MouseLeft
+        | Button::MouseRight
+        | Button::MouseMiddle
+        | Button::MouseSide
+        | Button::MouseExtra => {
+            return Err(Error::InvalidInput(InvalidInput::UnsupportedButton(button)))
+        }
+    };
+    Ok(input)
+}
+
 #[derive(Clone)]
 pub struct Bus {
     client: Arc<Mutex<vgm::Client>>,
@@ -22,83 +115,464 @@ impl Bus {
     }
 
     pub fn plug_in(&mut self) -> Result<Device, Error> {
+        self.plug_in_with(&DeviceConfig::default())
+    }
+
+    pub fn plug_in_with(&mut self, config: &DeviceConfig) -> Result<Device, Error> {
+        config.validate().map_err(Error::Unsupported)?;
+        if config.kind != DeviceKind::Gamepad {
+            return Err(Error::Unsupported(
+                "Only gamepads can be emulated through ViGEm".to_string(),
+            ));
+        }
+        if config.motion.is_some() {
+            return Err(Error::Unsupported(
+                "ViGEm's Xbox 360 controller has no motion sensors".to_string(),
+            ));
+        }
+        if config.touchpad.is_some() {
+            return Err(Error::Unsupported(
+                "ViGEm's Xbox 360 controller has no touchpad".to_string(),
+            ));
+        }
+        if !config.leds.is_empty() {
+            return Err(Error::Unsupported(
+                "ViGEm's Xbox 360 controller only has its player ring".to_string(),
+            ));
+        }
+        if let Some(button) = config
+            .extra_buttons
+            .iter()
+            .find(|button| press_input(**button).is_err())
+        {
+            return Err(Error::Unsupported(format!(
+                "ViGEm's Xbox 360 controller has no {:?} button",
+                button
+            )));
+        }
+
         let device = self.client.plug_in().map_with_vgp_error()?;
 
-        Ok(Device { device })
+        let device = Arc::new(Mutex::new(device));
+
+        Ok(Device {
+            input: InputHandle {
+                input: Arc::new(DeviceInput {
+                    device: Arc::clone(&device),
+                    buttons: config.buttons(),
+                    state: Mutex::new(InputState::default()),
+                    turbo: Mutex::new(None),
+                    held: Mutex::new(HashSet::new()),
+                    watchdog: Mutex::new(None),
+                }),
+            },
+            output: OutputHandle { device },
+        })
     }
 }
 
-pub struct Device {
-    device: vgm::Device,
+#[derive(Default)]
+struct InputState {
+    stick_processors: StickProcessors,
+    value_policy: ValuePolicy,
+    left_thumb_stick: (c_short, c_short),
+    right_thumb_stick: (c_short, c_short),
 }
 
-impl Device {
-    pub fn put_input(&mut self, input: Input) -> Result<(), Error> {
+impl InputState {
+    fn move_thumb_stick(&mut self, thumb_stick: ThumbStick, x: c_short, y: c_short) -> vgm::Input {
+        match thumb_stick {
+            ThumbStick::Left => {
+                self.left_thumb_stick = (x, y);
+                vgm::Input::MovedLeftThumbStick(x, y)
+            }
+            ThumbStick::Right => {
+                self.right_thumb_stick = (x, y);
+                vgm::Input::MovedRightThumbStick(x, y)
+            }
+        }
+    }
+}
+
+/// The input side of a device, shared with the threads that put inputs on the
+/// device's behalf.
+struct DeviceInput {
+    device: Arc<Mutex<vgm::Device>>,
+    buttons: HashSet<Button>,
+    state: Mutex<InputState>,
+    turbo: Mutex<Option<TurboWorker>>,
+    /// The buttons that were pressed and not released yet, including the ones
+    /// whose autofire is on.
+    held: Mutex<HashSet<Button>>,
+    watchdog: Mutex<Option<Watchdog>>,
+}
+
+impl DeviceInput {
+    fn check_button(&self, button: Button) -> Result<(), Error> {
+        if self.buttons.contains(&button) {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(InvalidInput::UnsupportedButton(button)))
+        }
+    }
+
+    fn set_turbo(&self, button: Button, turbo: Option<Turbo>) -> Result<(), Error> {
+        let mut worker = self.turbo.lock().unwrap();
+        if worker.is_none() {
+            if turbo.is_none() {
+                return Ok(());
+            }
+
+            let device = Arc::clone(&self.device);
+            *worker = Some(TurboWorker::new(Arc::new(move |button, pressed| {
+                let input = if pressed {
+                    press_input(button)?
+                } else {
+                    release_input(button)?
+                };
+                device.lock().unwrap().put_input(input).map_with_vgp_error()
+            })));
+        }
+
+        worker.as_ref().unwrap().set(button, turbo)
+    }
+
+    fn put_input(&self, input: Input) -> Result<(), Error> {
+        if let Some(watchdog) = &*self.watchdog.lock().unwrap() {
+            watchdog.feed();
+        }
+
         let input = match input {
-            Input::Move { thumb_stick, x, y } => match thumb_stick {
-                ThumbStick::Left => vgm::Input::MovedLeftThumbStick(
-                    (32767f32 * x) as c_short,
-                    (32767f32 * y) as c_short,
-                ),
-                ThumbStick::Right => vgm::Input::MovedRightThumbStick(
-                    (32767f32 * x) as c_short,
-                    (32767f32 * y) as c_short,
-                ),
-            },
-            Input::Press(button) => match button {
-                Button::DpadDown => vgm::Input::Pressed(vgm::Button::DpadDown),
-                Button::DpadUp => vgm::Input::Pressed(vgm::Button::DpadUp),
-                Button::DpadLeft => vgm::Input::Pressed(vgm::Button::DpadLeft),
-                Button::DpadRight => vgm::Input::Pressed(vgm::Button::DpadRight),
-                Button::North => vgm::Input::Pressed(vgm::Button::Y),
-                Button::South => vgm::Input::Pressed(vgm::Button::A),
-                Button::West => vgm::Input::Pressed(vgm::Button::B),
-                Button::East => vgm::Input::Pressed(vgm::Button::X),
-                Button::Start => vgm::Input::Pressed(vgm::Button::Start),
-                Button::Select => vgm::Input::Pressed(vgm::Button::Guide),
-                Button::TriggerLeft => vgm::Input::Pressed(vgm::Button::LeftShoulder),
-                Button::TriggerRight => vgm::Input::Pressed(vgm::Button::RightShoulder),
-                Button::TriggerLeft2 => vgm::Input::PressedLeftTrigger(127),
-                Button::TriggerRight2 => vgm::Input::PressedRightTrigger(127),
-                Button::ThumbStickLeft => vgm::Input::Pressed(vgm::Button::LeftThumb),
-                Button::ThumbStickRight => vgm::Input::Pressed(vgm::Button::RightThumb),
-            },
-            Input::Release(button) => match button {
-                Button::DpadDown => vgm::Input::Released(vgm::Button::DpadDown),
-                Button::DpadUp => vgm::Input::Released(vgm::Button::DpadUp),
-                Button::DpadLeft => vgm::Input::Released(vgm::Button::DpadLeft),
-                Button::DpadRight => vgm::Input::Released(vgm::Button::DpadRight),
-                Button::North => vgm::Input::Released(vgm::Button::Y),
-                Button::South => vgm::Input::Released(vgm::Button::A),
-                Button::West => vgm::Input::Released(vgm::Button::B),
-                Button::East => vgm::Input::Released(vgm::Button::X),
-                Button::Start => vgm::Input::Released(vgm::Button::Start),
-                Button::Select => vgm::Input::Released(vgm::Button::Guide),
-                Button::TriggerLeft => vgm::Input::Released(vgm::Button::LeftShoulder),
-                Button::TriggerRight => vgm::Input::Released(vgm::Button::RightShoulder),
-                Button::TriggerLeft2 => vgm::Input::PressedLeftTrigger(0),
-                Button::TriggerRight2 => vgm::Input::PressedRightTrigger(0),
-                Button::ThumbStickLeft => vgm::Input::Released(vgm::Button::LeftThumb),
-                Button::ThumbStickRight => vgm::Input::Released(vgm::Button::RightThumb),
-            },
+            Input::Move { thumb_stick, x, y } => {
+                let mut state = self.state.lock().unwrap();
+                let (x, y) = state
+                    .value_policy
+                    .apply(x, y)
+                    .map_err(Error::InvalidInput)?;
+                let (x, y) = state.stick_processors.get(thumb_stick).apply(x, y);
+                state.move_thumb_stick(
+                    thumb_stick,
+                    THUMB_STICK_AXIS_INFO.scale(x) as c_short,
+                    THUMB_STICK_AXIS_INFO.scale(y) as c_short,
+                )
+            }
+            Input::Axis { axis, value } => {
+                let (thumb_stick, horizontal) = match axis {
+                    Axis::X => (ThumbStick::Left, true),
+                    Axis::Y => (ThumbStick::Left, false),
+                    Axis::Rx => (ThumbStick::Right, true),
+                    Axis::Ry => (ThumbStick::Right, false),
+                    _ => return Err(Error::InvalidInput(InvalidInput::UnsupportedAxis(axis))),
+                };
+
+                let mut state = self.state.lock().unwrap();
+                let value = state
+                    .value_policy
+                    .apply_raw(axis, value, &THUMB_STICK_AXIS_INFO)
+                    .map_err(Error::InvalidInput)? as c_short;
+                let (x, y) = match thumb_stick {
+                    ThumbStick::Left => state.left_thumb_stick,
+                    ThumbStick::Right => state.right_thumb_stick,
+                };
+                if horizontal {
+                    state.move_thumb_stick(thumb_stick, value, y)
+                } else {
+                    state.move_thumb_stick(thumb_stick, x, value)
+                }
+            }
+            Input::Motion { .. } => {
+                return Err(Error::InvalidInput(InvalidInput::NoMotionSensors));
+            }
+            Input::Touch { .. } | Input::TouchUp { .. } | Input::TouchpadClick { .. } => {
+                return Err(Error::InvalidInput(InvalidInput::NoTouchpad));
+            }
+            Input::MouseMove { .. } | Input::Scroll { .. } => {
+                return Err(Error::InvalidInput(InvalidInput::NotAMouse));
+            }
+            Input::Press(button) => {
+                self.check_button(button)?;
+                self.held.lock().unwrap().insert(button);
+                if let Some(turbo) = &*self.turbo.lock().unwrap() {
+                    if turbo.press(button)? {
+                        return Ok(());
+                    }
+                }
+                press_input(button)?
+            }
+            Input::Release(button) => {
+                self.check_button(button)?;
+                self.held.lock().unwrap().remove(&button);
+                if let Some(turbo) = &*self.turbo.lock().unwrap() {
+                    if turbo.release(button)? {
+                        return Ok(());
+                    }
+                }
+                release_input(button)?
+            }
         };
-        self.device.put_input(input).map_with_vgp_error()
+        self.device
+            .lock()
+            .unwrap()
+            .put_input(input)
+            .map_with_vgp_error()
+    }
+
+    /// Releases every held button and centers both thumb sticks. Buttons with
+    /// autofire are released by their turbo worker.
+    fn reset(&self) -> Result<(), Error> {
+        let held: Vec<Button> = self.held.lock().unwrap().drain().collect();
+
+        let mut inputs = Vec::new();
+        {
+            let turbo = self.turbo.lock().unwrap();
+            for button in held {
+                if let Some(turbo) = &*turbo {
+                    if turbo.release(button)? {
+                        continue;
+                    }
+                }
+                inputs.push(release_input(button)?);
+            }
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            inputs.push(state.move_thumb_stick(ThumbStick::Left, 0, 0));
+            inputs.push(state.move_thumb_stick(ThumbStick::Right, 0, 0));
+        }
+
+        let mut device = self.device.lock().unwrap();
+        for input in inputs {
+            device.put_input(input).map_with_vgp_error()?;
+        }
+        Ok(())
+    }
+}
+
+/// The input half of a device. It can be cloned and moved to other threads,
+/// and keeps the device plugged in as long as it exists.
+#[derive(Clone)]
+pub struct InputHandle {
+    input: Arc<DeviceInput>,
+}
+
+impl InputHandle {
+    pub fn set_stick_processing(&self, thumb_stick: ThumbStick, processing: StickProcessing) {
+        let mut state = self.input.state.lock().unwrap();
+        state.stick_processors.set(thumb_stick, processing);
+    }
+
+    pub fn set_value_policy(&self, policy: ValuePolicy) {
+        self.input.state.lock().unwrap().value_policy = policy;
+    }
+
+    pub fn axis_info(&self, axis: Axis) -> Option<AxisInfo> {
+        match axis {
+            Axis::X | Axis::Y | Axis::Rx | Axis::Ry => Some(THUMB_STICK_AXIS_INFO),
+            _ => None,
+        }
+    }
+
+    pub fn set_turbo(&self, button: Button, turbo: Option<Turbo>) -> Result<(), Error> {
+        self.input.set_turbo(button, turbo)
+    }
+
+    pub fn put_input(&self, input: Input) -> Result<(), Error> {
+        self.input.put_input(input)
+    }
+
+    /// Releases every held button and centers every axis.
+    pub fn reset(&self) -> Result<(), Error> {
+        self.input.reset()
+    }
+
+    /// Only gamepads can be emulated through ViGEm.
+    pub fn type_text(&self, _text: &str) -> Result<(), Error> {
+        Err(Error::Unsupported("Only keyboards type text".to_string()))
+    }
+
+    /// Virtual Xbox 360 controllers have no battery.
+    pub fn set_battery(&self, _level: u8, _charging: bool) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "ViGEm devices cannot report a battery".to_string(),
+        ))
+    }
+
+    /// Resets the device when no input is put on it for `timeout`. `None`
+    /// turns the watchdog off.
+    pub fn set_watchdog(&self, timeout: Option<Duration>) {
+        let mut watchdog = self.input.watchdog.lock().unwrap();
+        match (timeout, &*watchdog) {
+            (Some(timeout), Some(running)) => running.set_timeout(timeout),
+            (Some(timeout), None) => {
+                let input = Arc::downgrade(&self.input);
+                *watchdog = Some(Watchdog::new(
+                    timeout,
+                    Arc::new(move || match input.upgrade() {
+                        Some(input) => input.reset(),
+                        None => Ok(()),
+                    }),
+                ));
+            }
+            (None, _) => *watchdog = None,
+        }
+    }
+
+    fn input_sink(&self) -> InputSink {
+        let input = Arc::downgrade(&self.input);
+        Arc::new(move |i| match input.upgrade() {
+            Some(input) => input.put_input(i),
+            None => Err(Error::Unplugged),
+        })
+    }
+
+    pub fn run_macro(&self, input_macro: &Macro) -> MacroHandle {
+        input_macro::run(input_macro, self.input_sink())
     }
 
+    /// Creates a scheduler that puts inputs on this device at `frame_rate`
+    /// frames per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_rate` is not a positive number.
+    pub fn scheduler(&self, frame_rate: f64) -> Scheduler {
+        Scheduler::new(frame_rate, self.input_sink())
+    }
+}
+
+/// The output half of a device.
+///
+/// ViGEm exposes a single handle per device, so both halves take turns on
+/// it: an input waits for a `get_output` that is in progress and the other
+/// way around. `get_output` never waits for an output to arrive, which keeps
+/// those turns short.
+pub struct OutputHandle {
+    device: Arc<Mutex<vgm::Device>>,
+}
+
+impl OutputHandle {
     pub fn get_output(&mut self) -> Result<Output, Error> {
-        match self.device.get_output() {
+        match self.device.lock().unwrap().get_output() {
             Some(output) => match output {
                 vgm::Output::Rumble(large_motor, small_motor) => Ok(Output::Rumble {
                     large_motor: large_motor.into(),
                     small_motor: small_motor.into(),
                 }),
-                vgm::Output::Led(_) => Ok(Output::Unsupported),
+                vgm::Output::Led(led) => Ok(player_led(led)),
             },
             None => Ok(Output::None),
         }
     }
+}
+
+/// The player the ring of an Xbox 360 controller shows, from the state that
+/// XInput sets it to, as the bit of that player. States 2 to 5 flash before
+/// they stay on like 6 to 9, and the others are animations that show no
+/// player.
+fn player_led(led: u8) -> Output {
+    match led {
+        0 => Output::PlayerLeds(0),
+        2..=5 => Output::PlayerLeds(1 << (led - 2)),
+        6..=9 => Output::PlayerLeds(1 << (led - 6)),
+        _ => Output::Unsupported,
+    }
+}
+
+pub struct Device {
+    input: InputHandle,
+    output: OutputHandle,
+}
+
+impl Device {
+    /// Splits the device into its input and output halves, so that inputs can
+    /// be put on one thread while outputs are read on another. The device is
+    /// unplugged once both halves and every clone of the input half are
+    /// dropped.
+    ///
+    /// Unlike on Linux, the halves are not serviced concurrently; see
+    /// [`OutputHandle`].
+    pub fn split(self) -> (InputHandle, OutputHandle) {
+        (self.input, self.output)
+    }
+
+    pub fn set_stick_processing(&mut self, thumb_stick: ThumbStick, processing: StickProcessing) {
+        self.input.set_stick_processing(thumb_stick, processing)
+    }
+
+    pub fn set_value_policy(&mut self, policy: ValuePolicy) {
+        self.input.set_value_policy(policy)
+    }
+
+    pub fn axis_info(&self, axis: Axis) -> Option<AxisInfo> {
+        self.input.axis_info(axis)
+    }
+
+    pub fn set_turbo(&mut self, button: Button, turbo: Option<Turbo>) -> Result<(), Error> {
+        self.input.set_turbo(button, turbo)
+    }
+
+    pub fn put_input(&mut self, input: Input) -> Result<(), Error> {
+        self.input.put_input(input)
+    }
+
+    /// Releases every held button and centers every axis.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.input.reset()
+    }
+
+    /// Only gamepads can be emulated through ViGEm.
+    pub fn type_text(&mut self, text: &str) -> Result<(), Error> {
+        self.input.type_text(text)
+    }
+
+    /// Virtual Xbox 360 controllers have no battery.
+    pub fn set_battery(&mut self, level: u8, charging: bool) -> Result<(), Error> {
+        self.input.set_battery(level, charging)
+    }
+
+    /// Resets the device when no input is put on it for `timeout`. `None`
+    /// turns the watchdog off.
+    pub fn set_watchdog(&mut self, timeout: Option<Duration>) {
+        self.input.set_watchdog(timeout)
+    }
+
+    pub fn run_macro(&self, input_macro: &Macro) -> MacroHandle {
+        self.input.run_macro(input_macro)
+    }
+
+    /// Creates a scheduler that puts inputs on this device at `frame_rate`
+    /// frames per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_rate` is not a positive number.
+    pub fn scheduler(&self, frame_rate: f64) -> Scheduler {
+        self.input.scheduler(frame_rate)
+    }
+
+    pub fn get_output(&mut self) -> Result<Output, Error> {
+        self.output.get_output()
+    }
 
     pub fn unplug(self) -> Result<(), Error> {
-        self.device.unplug().map_with_vgp_error()
+        self.input.input.watchdog.lock().unwrap().take();
+        self.input.input.turbo.lock().unwrap().take();
+        drop(self.input);
+
+        match Arc::try_unwrap(self.output.device) {
+            Ok(device) => device.into_inner().unwrap().unplug().map_with_vgp_error(),
+            Err(_) => Err(Error::Unknown(
+                "Device is still in use by another thread.".to_string(),
+            )),
+        }
+    }
+
+    /// Releases every held button and centers every axis before unplugging,
+    /// so that consumers that keep the last state of the device are not left
+    /// with stuck inputs.
+    pub fn release_and_unplug(self) -> Result<(), Error> {
+        let reset = self.input.reset();
+        let unplug = self.unplug();
+        reset.and(unplug)
     }
 }